@@ -41,7 +41,7 @@ fn part1(monkeys: &Vec<Monkey>) -> u64 {
 #[aoc(day11, part2)]
 fn part2(monkeys: &Vec<Monkey>) -> u64 {
     let mut monkeys = monkeys.to_owned();
-    let common_divisor: u64 = monkeys.iter().map(|m| m.test.divisor as u64).product();
+    let common_divisor: u64 = monkeys.iter().map(|m| m.test.divisor).product();
     for _round in 0..10_000 {
         for monkey_idx in 0..monkeys.len() {
             let monkey = monkeys[monkey_idx].clone();
@@ -130,7 +130,7 @@ struct Test {
 
 impl Test {
     fn apply(&self, val: u64) -> u8 {
-        if val % self.divisor == 0 {
+        if val.is_multiple_of(self.divisor) {
             self.if_divisible
         } else {
             self.if_not_divisible
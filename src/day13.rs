@@ -19,7 +19,7 @@ fn parse_input(input: &str) -> Vec<(Packet, Packet)> {
 }
 
 #[aoc(day13, part1)]
-fn part1(packets: &Vec<(Packet, Packet)>) -> u64 {
+fn part1(packets: &[(Packet, Packet)]) -> u64 {
     packets
         .iter()
         .enumerate()
@@ -31,7 +31,7 @@ fn part1(packets: &Vec<(Packet, Packet)>) -> u64 {
 }
 
 #[aoc(day13, part2)]
-fn part2(packets: &Vec<(Packet, Packet)>) -> u64 {
+fn part2(packets: &[(Packet, Packet)]) -> u64 {
     let mut packets: Vec<Packet> = packets
         .iter()
         .flat_map(|(first, second)| once(first.clone()).chain(once(second.clone())))
@@ -71,12 +71,12 @@ fn compare_data(a: &Data, b: &Data) -> Option<Ordering> {
     match (a, b) {
         (Data::Int(a), Data::Int(b)) => a.partial_cmp(b),
         (Data::List(a), Data::List(b)) => compare_vec(a, b),
-        (Data::Int(_), Data::List(b)) => compare_vec(&vec![a.clone()], b),
-        (Data::List(a), Data::Int(_)) => compare_vec(a, &vec![b.clone()]),
+        (Data::Int(_), Data::List(b)) => compare_vec(std::slice::from_ref(a), b),
+        (Data::List(a), Data::Int(_)) => compare_vec(a, std::slice::from_ref(b)),
     }
 }
 
-fn compare_vec(a: &Vec<Data>, b: &Vec<Data>) -> Option<Ordering> {
+fn compare_vec(a: &[Data], b: &[Data]) -> Option<Ordering> {
     for (data_a, data_b) in a.iter().zip(b) {
         let comparison = compare_data(data_a, data_b);
         if comparison != Some(Ordering::Equal) {
@@ -1,124 +1,347 @@
-use std::str::FromStr;
+use std::collections::{HashMap, HashSet};
 
+use anyhow::{bail, Context};
 use aoc_runner_derive::aoc;
+use itertools::Itertools;
+use parse_display::Display;
 
 #[aoc(day2, part1)]
 fn part1(input: &str) -> u64 {
-    input
-        .lines()
-        .map(|l| {
-            let line = l.split_once(' ').unwrap();
-            let their_action = line.0.parse().unwrap();
-            let my_action = line.1.parse().unwrap();
-            points_for_match(&their_action, &my_action)
-        })
-        .sum()
+    Game::rock_paper_scissors().score_as_moves(input).unwrap()
 }
 
 #[aoc(day2, part2)]
 fn part2(input: &str) -> u64 {
-    input
-        .lines()
-        .map(|l| {
-            let line = l.split_once(' ').unwrap();
-            let their_action = line.0.parse::<Action>().unwrap();
-            let desired_outcome = line.1.parse().unwrap();
-            let my_action = action_for_outcome(&their_action, &desired_outcome);
-            points_for_match(&their_action, &my_action)
-        })
-        .sum()
+    Game::rock_paper_scissors().score_as_outcomes(input).unwrap()
 }
 
-#[derive(Clone)]
-enum Action {
-    Rock,
-    Paper,
-    Scissors,
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Loss,
+    Draw,
+    Win,
 }
 
-impl FromStr for Action {
-    type Err = anyhow::Error;
+/// A move in a [`Game`], with the symbols that stand for it in the strategy guide.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveConfig {
+    pub name: String,
+    pub their_symbol: String,
+    pub my_symbol: String,
+    pub points: u64,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "A" | "X" => Ok(Action::Rock),
-            "B" | "Y" => Ok(Action::Paper),
-            "C" | "Z" => Ok(Action::Scissors),
-            _ => unreachable!(),
+impl MoveConfig {
+    pub fn new(name: &str, their_symbol: &str, my_symbol: &str, points: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            their_symbol: their_symbol.to_string(),
+            my_symbol: my_symbol.to_string(),
+            points,
         }
     }
 }
 
-impl From<u8> for Action {
-    fn from(x: u8) -> Self {
-        match x {
-            0 => Action::Rock,
-            1 => Action::Paper,
-            2 => Action::Scissors,
-            _ => unreachable!(),
+/// Points for the outcome of a round, which are added to the points for the move I played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutcomePoints {
+    pub loss: u64,
+    pub draw: u64,
+    pub win: u64,
+}
+
+impl OutcomePoints {
+    pub fn get(&self, outcome: Outcome) -> u64 {
+        match outcome {
+            Outcome::Loss => self.loss,
+            Outcome::Draw => self.draw,
+            Outcome::Win => self.win,
         }
     }
 }
 
-#[derive(Clone)]
-enum Outcome {
-    Draw,
-    Win,
-    Loss,
+/// A game like rock-paper-scissors with an odd number of moves in a cycle. Each move beats the half of the other
+/// moves that come right before it in the cycle, and loses to the half that come right after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    moves: Vec<MoveConfig>,
+    outcome_points: OutcomePoints,
+    /// Symbols for the outcome I need, when the strategy guide is read as outcomes.
+    outcome_symbols: Vec<(String, Outcome)>,
 }
 
-impl FromStr for Outcome {
-    type Err = anyhow::Error;
+impl Game {
+    /// Creates a game with `moves` in cycle order. Fails if there are fewer than three moves or an even number of
+    /// moves, if a symbol is used for more than one move or outcome, or if the outcome symbols don't stand for each
+    /// outcome exactly once.
+    pub fn new(
+        moves: Vec<MoveConfig>,
+        outcome_points: OutcomePoints,
+        outcome_symbols: Vec<(String, Outcome)>,
+    ) -> anyhow::Result<Self> {
+        if moves.len() < 3 || moves.len().is_multiple_of(2) {
+            bail!(
+                "A cyclic game needs an odd number of at least 3 moves, got {}",
+                moves.len()
+            );
+        }
+        let unique = |symbols: Vec<&String>| symbols.iter().collect::<HashSet<_>>().len() == symbols.len();
+        if !unique(moves.iter().map(|move_| &move_.their_symbol).collect()) {
+            bail!("The opponent's symbols are not unique");
+        }
+        if !unique(moves.iter().map(|move_| &move_.my_symbol).collect()) {
+            bail!("My symbols are not unique");
+        }
+        if !unique(outcome_symbols.iter().map(|(symbol, _)| symbol).collect()) {
+            bail!("The outcome symbols are not unique");
+        }
+        for outcome in [Outcome::Loss, Outcome::Draw, Outcome::Win] {
+            let count = outcome_symbols.iter().filter(|&&(_, other)| other == outcome).count();
+            if count != 1 {
+                bail!("Outcome {outcome} has {count} symbols instead of 1");
+            }
+        }
+        Ok(Self {
+            moves,
+            outcome_points,
+            outcome_symbols,
+        })
+    }
+
+    /// The game from the puzzle: `A`/`X` for rock, `B`/`Y` for paper and `C`/`Z` for scissors, or `X`/`Y`/`Z`
+    /// for a loss, draw or win.
+    pub fn rock_paper_scissors() -> Self {
+        Self::new(
+            vec![
+                MoveConfig::new("Rock", "A", "X", 1),
+                MoveConfig::new("Paper", "B", "Y", 2),
+                MoveConfig::new("Scissors", "C", "Z", 3),
+            ],
+            OutcomePoints {
+                loss: 0,
+                draw: 3,
+                win: 6,
+            },
+            vec![
+                ("X".to_string(), Outcome::Loss),
+                ("Y".to_string(), Outcome::Draw),
+                ("Z".to_string(), Outcome::Win),
+            ],
+        )
+        .unwrap()
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "X" => Ok(Outcome::Loss),
-            "Y" => Ok(Outcome::Draw),
-            "Z" => Ok(Outcome::Win),
-            _ => unreachable!(),
+    /// Rock-paper-scissors-lizard-Spock, with `A` through `E` for the opponent and `V` through `Z` for me in the
+    /// order rock, Spock, paper, lizard, scissors. Outcomes use `X`/`Y`/`Z` like the puzzle.
+    pub fn rock_paper_scissors_lizard_spock() -> Self {
+        Self::new(
+            vec![
+                MoveConfig::new("Rock", "A", "V", 1),
+                MoveConfig::new("Spock", "B", "W", 2),
+                MoveConfig::new("Paper", "C", "X", 3),
+                MoveConfig::new("Lizard", "D", "Y", 4),
+                MoveConfig::new("Scissors", "E", "Z", 5),
+            ],
+            OutcomePoints {
+                loss: 0,
+                draw: 3,
+                win: 6,
+            },
+            vec![
+                ("X".to_string(), Outcome::Loss),
+                ("Y".to_string(), Outcome::Draw),
+                ("Z".to_string(), Outcome::Win),
+            ],
+        )
+        .unwrap()
+    }
+
+    pub fn moves(&self) -> &[MoveConfig] {
+        &self.moves
+    }
+
+    /// The outcome for me when I play move `mine` against move `theirs`, both indices into [`Game::moves`].
+    pub fn outcome(&self, theirs: usize, mine: usize) -> Outcome {
+        let n = self.moves.len();
+        match (mine + n - theirs) % n {
+            0 => Outcome::Draw,
+            distance if distance <= n / 2 => Outcome::Win,
+            _ => Outcome::Loss,
         }
     }
-}
 
-fn calculate_outcome(their_action: &Action, my_action: &Action) -> Outcome {
-    let their_action_idx = their_action.clone() as u8;
-    let my_action_idx = my_action.clone() as u8;
-    if my_action_idx == (their_action_idx + 1) % 3 {
-        Outcome::Win
-    } else if my_action_idx == their_action_idx {
-        Outcome::Draw
-    } else {
-        Outcome::Loss
+    pub fn score(&self, theirs: usize, mine: usize) -> u64 {
+        self.moves[mine].points + self.outcome_points.get(self.outcome(theirs, mine))
     }
-}
 
-fn points_for_action(action: &Action) -> u64 {
-    match action {
-        Action::Rock => 1,
-        Action::Paper => 2,
-        Action::Scissors => 3,
+    /// The move that gets me `outcome` against move `theirs`. If several moves do, the one worth the most points is
+    /// played.
+    pub fn move_for_outcome(&self, theirs: usize, outcome: Outcome) -> usize {
+        (0..self.moves.len())
+            .filter(|&mine| self.outcome(theirs, mine) == outcome)
+            .max_by_key(|&mine| self.moves[mine].points)
+            .expect("games have at least 3 moves, so every outcome is possible")
     }
-}
 
-fn points_for_outcome(outcome: &Outcome) -> u64 {
-    match outcome {
-        Outcome::Win => 6,
-        Outcome::Draw => 3,
-        Outcome::Loss => 0,
+    /// Total score when the second column of the strategy guide is the move I should play.
+    pub fn score_as_moves(&self, input: &str) -> anyhow::Result<u64> {
+        self.total_score(input, |_, symbol| {
+            let mine = self.moves.iter().position(|move_| move_.my_symbol == symbol);
+            mine.with_context(|| format!("'{symbol}' is not one of my moves"))
+        })
+    }
+
+    /// Total score when the second column of the strategy guide is the outcome I need.
+    pub fn score_as_outcomes(&self, input: &str) -> anyhow::Result<u64> {
+        self.total_score(input, |theirs, symbol| {
+            let outcome = self
+                .outcome_symbols
+                .iter()
+                .find(|(outcome_symbol, _)| outcome_symbol == symbol);
+            let (_, outcome) = outcome.with_context(|| format!("'{symbol}' is not an outcome"))?;
+            Ok(self.move_for_outcome(theirs, *outcome))
+        })
+    }
+
+    /// Scores the strategy guide under every way of reading the second column: each permutation of my moves over my
+    /// symbols, and each permutation of the outcomes over the outcome symbols.
+    pub fn decryptions(&self, input: &str) -> anyhow::Result<Vec<Decryption>> {
+        let rounds = self.count_rounds(input)?;
+        let score = |my_move: &dyn Fn(usize, &str) -> usize| {
+            rounds
+                .iter()
+                .map(|(&(theirs, symbol), &count)| count * self.score(theirs, my_move(theirs, symbol)))
+                .sum()
+        };
+
+        let my_symbols = self.moves.iter().map(|move_| move_.my_symbol.as_str()).collect_vec();
+        let as_moves = (0..self.moves.len()).permutations(self.moves.len()).map(|moves| {
+            let mine = |symbol: &str| moves[my_symbols.iter().position(|&s| s == symbol).unwrap()];
+            Decryption {
+                mapping: my_symbols
+                    .iter()
+                    .zip(&moves)
+                    .map(|(symbol, &mine)| (symbol.to_string(), Meaning::Move(self.moves[mine].name.clone())))
+                    .collect(),
+                score: score(&|_, symbol| mine(symbol)),
+            }
+        });
+
+        let outcome_symbols = self
+            .outcome_symbols
+            .iter()
+            .map(|(symbol, _)| symbol.as_str())
+            .collect_vec();
+        let outcomes = self.outcome_symbols.iter().map(|&(_, outcome)| outcome).collect_vec();
+        let as_outcomes = outcomes.iter().copied().permutations(outcomes.len()).map(|outcomes| {
+            let outcome = |symbol: &str| outcomes[outcome_symbols.iter().position(|&s| s == symbol).unwrap()];
+            Decryption {
+                mapping: outcome_symbols
+                    .iter()
+                    .zip(&outcomes)
+                    .map(|(symbol, &outcome)| (symbol.to_string(), Meaning::Outcome(outcome)))
+                    .collect(),
+                score: score(&|theirs, symbol| self.move_for_outcome(theirs, outcome(symbol))),
+            }
+        });
+
+        // A reading only applies if it gives a meaning to every symbol in the guide
+        let symbols_are_outcomes = rounds.keys().all(|(_, symbol)| outcome_symbols.contains(symbol));
+        let symbols_are_moves = rounds.keys().all(|(_, symbol)| my_symbols.contains(symbol));
+        let mut decryptions = Vec::new();
+        if symbols_are_moves {
+            decryptions.extend(as_moves);
+        }
+        if symbols_are_outcomes {
+            decryptions.extend(as_outcomes);
+        }
+        if decryptions.is_empty() {
+            bail!("The strategy guide uses symbols that are neither my moves nor outcomes");
+        }
+        Ok(decryptions)
+    }
+
+    /// The reading of the strategy guide that gives me the highest score.
+    pub fn best_decryption(&self, input: &str) -> anyhow::Result<Decryption> {
+        Ok(self
+            .decryptions(input)?
+            .into_iter()
+            .max_by_key(|decryption| decryption.score)
+            .unwrap())
+    }
+
+    /// The readings of the strategy guide that give me exactly `score`.
+    pub fn decryptions_with_score(&self, input: &str, score: u64) -> anyhow::Result<Vec<Decryption>> {
+        Ok(self
+            .decryptions(input)?
+            .into_iter()
+            .filter(|decryption| decryption.score == score)
+            .collect())
+    }
+
+    /// Counts how often each combination of their move and my symbol occurs in the strategy guide.
+    fn count_rounds<'a>(&self, input: &'a str) -> anyhow::Result<HashMap<(usize, &'a str), u64>> {
+        let mut rounds = HashMap::new();
+        for (line_idx, line) in input.lines().enumerate() {
+            let round = || -> anyhow::Result<(usize, &'a str)> {
+                let (theirs, mine) = line.split_once(' ').context("expected two symbols")?;
+                let theirs = self.their_move(theirs)?;
+                Ok((theirs, mine))
+            };
+            let round = round().with_context(|| format!("Invalid round on line {}", line_idx + 1))?;
+            *rounds.entry(round).or_default() += 1;
+        }
+        Ok(rounds)
+    }
+
+    fn their_move(&self, symbol: &str) -> anyhow::Result<usize> {
+        self.moves
+            .iter()
+            .position(|move_| move_.their_symbol == symbol)
+            .with_context(|| format!("'{symbol}' is not one of the opponent's moves"))
+    }
+
+    /// Adds up the score of every round, using `my_move` to pick my move from their move and the second symbol.
+    fn total_score(&self, input: &str, my_move: impl Fn(usize, &str) -> anyhow::Result<usize>) -> anyhow::Result<u64> {
+        input
+            .lines()
+            .enumerate()
+            .map(|(line_idx, line)| {
+                let round = || -> anyhow::Result<u64> {
+                    let (theirs, mine) = line.split_once(' ').context("expected two symbols")?;
+                    let theirs = self.their_move(theirs)?;
+                    Ok(self.score(theirs, my_move(theirs, mine)?))
+                };
+                round().with_context(|| format!("Invalid round on line {}", line_idx + 1))
+            })
+            .sum()
     }
 }
 
-fn points_for_match(their_action: &Action, my_action: &Action) -> u64 {
-    let poits_for_action = points_for_action(my_action);
-    let poits_for_result = points_for_outcome(&calculate_outcome(their_action, my_action));
-    poits_for_action + poits_for_result
+/// What a symbol in the second column of the strategy guide stands for.
+#[derive(Display, Debug, Clone, PartialEq, Eq)]
+#[display("{0}")]
+pub enum Meaning {
+    Move(String),
+    Outcome(Outcome),
 }
 
-fn action_for_outcome(their_action: &Action, outcome: &Outcome) -> Action {
-    let their_action_idx = their_action.clone() as u8;
-    let outcome_idx = outcome.clone() as u8;
-    let my_action = (their_action_idx + outcome_idx) % 3;
-    my_action.into()
+/// A way of reading the strategy guide, with the score I get when following it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decryption {
+    pub mapping: Vec<(String, Meaning)>,
+    pub score: u64,
+}
+
+impl std::fmt::Display for Decryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mapping = self
+            .mapping
+            .iter()
+            .map(|(symbol, meaning)| format!("{symbol}={meaning}"))
+            .join(", ");
+        write!(f, "{mapping}: {}", self.score)
+    }
 }
 
 #[cfg(test)]
@@ -151,4 +374,125 @@ mod tests {
     fn part2_solution() {
         assert_eq!(part2(PUZZLE_INPUT), 12424);
     }
+
+    #[test]
+    fn lizard_spock_rules() {
+        let game = Game::rock_paper_scissors_lizard_spock();
+        let idx = |name: &str| game.moves().iter().position(|move_| move_.name == name).unwrap();
+        for (winner, loser) in [
+            ("Scissors", "Paper"),
+            ("Paper", "Rock"),
+            ("Rock", "Lizard"),
+            ("Lizard", "Spock"),
+            ("Spock", "Scissors"),
+            ("Scissors", "Lizard"),
+            ("Lizard", "Paper"),
+            ("Paper", "Spock"),
+            ("Spock", "Rock"),
+            ("Rock", "Scissors"),
+        ] {
+            assert_eq!(game.outcome(idx(loser), idx(winner)), Outcome::Win);
+            assert_eq!(game.outcome(idx(winner), idx(loser)), Outcome::Loss);
+        }
+        assert_eq!(game.outcome(idx("Spock"), idx("Spock")), Outcome::Draw);
+        // Both paper and Spock beat rock, and paper is worth more
+        assert_eq!(game.move_for_outcome(idx("Rock"), Outcome::Win), idx("Paper"));
+    }
+
+    #[test]
+    fn lizard_spock_scores() {
+        let game = Game::rock_paper_scissors_lizard_spock();
+        assert_eq!(
+            game.score_as_moves("A W\nE V\nD Y").unwrap(),
+            (2 + 6) + (1 + 6) + (4 + 3)
+        );
+        assert_eq!(game.score_as_outcomes("A Z\nE X\nD Y").unwrap(), (3 + 6) + 4 + (4 + 3));
+    }
+
+    #[test]
+    fn decrypt_example() {
+        let game = Game::rock_paper_scissors();
+        let decryptions = game.decryptions(EXAMPLE_INPUT).unwrap();
+        assert_eq!(decryptions.len(), 12);
+        assert_eq!(decryptions[0].to_string(), "X=Rock, Y=Paper, Z=Scissors: 15");
+        assert_eq!(decryptions[6].to_string(), "X=Loss, Y=Draw, Z=Win: 12");
+        assert_eq!(
+            game.best_decryption(EXAMPLE_INPUT).unwrap().to_string(),
+            "X=Scissors, Y=Paper, Z=Rock: 24"
+        );
+        let matching = game.decryptions_with_score(EXAMPLE_INPUT, 15).unwrap();
+        assert_eq!(matching.len(), 8);
+        assert_eq!(matching[0], decryptions[0]);
+    }
+
+    #[test]
+    fn decrypt_puzzle() {
+        let game = Game::rock_paper_scissors();
+        let decryptions = game.decryptions(PUZZLE_INPUT).unwrap();
+        assert!(decryptions.iter().any(|decryption| decryption.score == 13565));
+        assert!(decryptions.iter().any(|decryption| decryption.score == 12424));
+        let best = game.best_decryption(PUZZLE_INPUT).unwrap();
+        assert!(decryptions.iter().all(|decryption| decryption.score <= best.score));
+
+        // Symbols that aren't outcomes leave only the move readings
+        let game = Game::rock_paper_scissors_lizard_spock();
+        assert_eq!(game.decryptions("A V\nB W").unwrap().len(), 120);
+        assert!(game.decryptions("A Q").is_err());
+    }
+
+    #[test]
+    fn invalid_games_and_rounds() {
+        let moves = vec![
+            MoveConfig::new("Rock", "A", "X", 1),
+            MoveConfig::new("Paper", "B", "Y", 2),
+        ];
+        let points = OutcomePoints {
+            loss: 0,
+            draw: 3,
+            win: 6,
+        };
+        let outcome_symbols = vec![
+            ("X".to_string(), Outcome::Loss),
+            ("Y".to_string(), Outcome::Draw),
+            ("Z".to_string(), Outcome::Win),
+        ];
+        let error = |moves: &[MoveConfig], outcome_symbols: &[(String, Outcome)]| {
+            Game::new(moves.to_vec(), points, outcome_symbols.to_vec())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error(&moves, &outcome_symbols),
+            "A cyclic game needs an odd number of at least 3 moves, got 2"
+        );
+        assert_eq!(
+            error(&moves[..1], &outcome_symbols),
+            "A cyclic game needs an odd number of at least 3 moves, got 1"
+        );
+        let mut duplicate = moves.clone();
+        duplicate.push(MoveConfig::new("Scissors", "C", "X", 3));
+        assert_eq!(error(&duplicate, &outcome_symbols), "My symbols are not unique");
+        let mut moves = moves;
+        moves.push(MoveConfig::new("Scissors", "C", "Z", 3));
+        assert_eq!(
+            error(&moves, &outcome_symbols[..2]),
+            "Outcome Win has 0 symbols instead of 1"
+        );
+        let two_draws = [
+            ("X".to_string(), Outcome::Draw),
+            ("Y".to_string(), Outcome::Draw),
+            ("Z".to_string(), Outcome::Win),
+        ];
+        assert_eq!(error(&moves, &two_draws), "Outcome Loss has 0 symbols instead of 1");
+        assert!(Game::new(moves, points, outcome_symbols).is_ok());
+
+        let game = Game::rock_paper_scissors();
+        let error = |input: &str| format!("{:#}", game.score_as_outcomes(input).unwrap_err());
+        assert_eq!(
+            error("A Y\nD X"),
+            "Invalid round on line 2: 'D' is not one of the opponent's moves"
+        );
+        assert_eq!(error("A W"), "Invalid round on line 1: 'W' is not an outcome");
+        assert_eq!(error("AX"), "Invalid round on line 1: expected two symbols");
+    }
 }
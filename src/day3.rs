@@ -1,8 +1,257 @@
+use std::{fmt, ops::BitAnd, str::FromStr};
+
+use anyhow::{bail, Context};
 use aoc_runner_derive::aoc;
 use itertools::Itertools;
+use parse_display::Display;
 
 #[aoc(day3, part1)]
 fn part1(input: &str) -> u64 {
+    let rucksacks = input.lines().collect_vec();
+    common_items(&rucksacks, 2)
+        .unwrap()
+        .into_iter()
+        .map(ItemSet::priority_sum)
+        .sum()
+}
+
+#[aoc(day3, part2)]
+fn part2(input: &str) -> u64 {
+    let rucksacks = input.lines().collect_vec();
+    badges(&rucksacks, 3)
+        .unwrap()
+        .into_iter()
+        .map(|badge| priority(badge).unwrap())
+        .sum()
+}
+
+/// Returns the items that are in every compartment, for each rucksack. Every rucksack must have the same number
+/// of items in each of its `compartments` compartments.
+pub fn common_items(rucksacks: &[&str], compartments: usize) -> anyhow::Result<Vec<ItemSet>> {
+    assert!(compartments > 0, "a rucksack needs at least one compartment");
+    rucksacks
+        .iter()
+        .enumerate()
+        .map(|(idx, rucksack)| {
+            let len = rucksack.len();
+            if !len.is_multiple_of(compartments) {
+                bail!(
+                    "Rucksack {} has {len} items, which can't be split into {compartments} equal compartments",
+                    idx + 1
+                );
+            }
+            let compartment_len = len / compartments;
+            (0..compartments)
+                .map(|compartment| {
+                    rucksack
+                        .get(compartment * compartment_len..(compartment + 1) * compartment_len)
+                        .context("compartment does not start at an item")?
+                        .parse::<ItemSet>()
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+                .map(ItemSet::intersection_of)
+                .with_context(|| format!("Invalid rucksack {}", idx + 1))
+        })
+        .collect()
+}
+
+/// Returns the items that all rucksacks in a group have in common, for each group of `group_size` consecutive
+/// rucksacks.
+pub fn group_common_items(rucksacks: &[&str], group_size: usize) -> anyhow::Result<Vec<ItemSet>> {
+    assert!(group_size > 0, "a group needs at least one elf");
+    if !rucksacks.len().is_multiple_of(group_size) {
+        bail!(
+            "{} rucksacks can't be split into groups of {group_size}",
+            rucksacks.len()
+        );
+    }
+    let sets = rucksacks
+        .iter()
+        .enumerate()
+        .map(|(idx, rucksack)| {
+            rucksack
+                .parse::<ItemSet>()
+                .with_context(|| format!("Invalid rucksack {}", idx + 1))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(sets
+        .chunks(group_size)
+        .map(|group| ItemSet::intersection_of(group.iter().copied()))
+        .collect())
+}
+
+/// A group that doesn't have exactly one item in common, with the group number (starting at 1).
+#[derive(Display, Debug, Clone, PartialEq, Eq)]
+#[display("group {group} has {kind}")]
+pub struct BadgeIssue {
+    pub group: usize,
+    pub kind: BadgeIssueKind,
+}
+
+#[derive(Display, Debug, Clone, PartialEq, Eq)]
+pub enum BadgeIssueKind {
+    #[display("no badge")]
+    NoBadge,
+    #[display("multiple badge candidates {0}")]
+    MultipleBadges(ItemSet),
+}
+
+/// Finds the groups whose common items don't identify a single badge.
+pub fn badge_issues(group_items: &[ItemSet]) -> Vec<BadgeIssue> {
+    group_items
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, &items)| {
+            let kind = match items.len() {
+                0 => BadgeIssueKind::NoBadge,
+                1 => return None,
+                _ => BadgeIssueKind::MultipleBadges(items),
+            };
+            Some(BadgeIssue { group: idx + 1, kind })
+        })
+        .collect()
+}
+
+/// Returns the badge of each group of `group_size` consecutive rucksacks, failing with all groups that don't have
+/// exactly one badge.
+pub fn badges(rucksacks: &[&str], group_size: usize) -> anyhow::Result<Vec<char>> {
+    let group_items = group_common_items(rucksacks, group_size)?;
+    let issues = badge_issues(&group_items);
+    if !issues.is_empty() {
+        bail!("{}", issues.iter().join(", "));
+    }
+    Ok(group_items
+        .into_iter()
+        .map(|items| items.items().next().unwrap())
+        .collect())
+}
+
+/// Splits rucksacks in any order into groups of `group_size` that each have exactly one item in common. Returns
+/// the indices of the rucksacks in each group, or `None` if there is no such split.
+pub fn find_badge_groups(rucksacks: &[ItemSet], group_size: usize) -> Option<Vec<Vec<usize>>> {
+    assert!(group_size > 0, "a group needs at least one elf");
+    if !rucksacks.len().is_multiple_of(group_size) {
+        return None;
+    }
+    let mut candidates = Vec::new();
+    candidate_groups(rucksacks, group_size, &mut Vec::new(), ItemSet::ALL, &mut candidates);
+
+    let mut groups_with = vec![Vec::new(); rucksacks.len()];
+    for (group_idx, group) in candidates.iter().enumerate() {
+        for &idx in group {
+            groups_with[idx].push(group_idx);
+        }
+    }
+    let mut search = BadgeGroupSearch {
+        open_groups: groups_with.iter().map(Vec::len).collect(),
+        blocked: vec![0; candidates.len()],
+        assigned: vec![false; rucksacks.len()],
+        candidates,
+        groups_with,
+        groups: Vec::new(),
+    };
+    search.fill_groups().then(|| {
+        search
+            .groups
+            .iter()
+            .map(|&group_idx| search.candidates[group_idx].clone())
+            .collect()
+    })
+}
+
+/// Collects all groups of rucksacks (with indices in ascending order) that have exactly one item in common.
+fn candidate_groups(
+    rucksacks: &[ItemSet],
+    group_size: usize,
+    group: &mut Vec<usize>,
+    common: ItemSet,
+    candidates: &mut Vec<Vec<usize>>,
+) {
+    if group.len() == group_size {
+        if common.len() == 1 {
+            candidates.push(group.clone());
+        }
+        return;
+    }
+    let next = group.last().map_or(0, |last| last + 1);
+    for idx in next..rucksacks.len() {
+        let common = common & rucksacks[idx];
+        // Common items only get fewer as the group grows
+        if !common.is_empty() {
+            group.push(idx);
+            candidate_groups(rucksacks, group_size, group, common, candidates);
+            group.pop();
+        }
+    }
+}
+
+/// Exact cover search over the candidate groups. It always continues with the rucksack that is left with the
+/// fewest candidate groups, so dead ends are found early.
+struct BadgeGroupSearch {
+    candidates: Vec<Vec<usize>>,
+    /// Indices of the candidate groups that each rucksack is in.
+    groups_with: Vec<Vec<usize>>,
+    /// Number of candidate groups of each rucksack that only have rucksacks that aren't assigned yet.
+    open_groups: Vec<usize>,
+    /// Number of assigned rucksacks in each candidate group.
+    blocked: Vec<usize>,
+    assigned: Vec<bool>,
+    groups: Vec<usize>,
+}
+
+impl BadgeGroupSearch {
+    fn fill_groups(&mut self) -> bool {
+        let Some(rucksack) = (0..self.assigned.len())
+            .filter(|&idx| !self.assigned[idx])
+            .min_by_key(|&idx| self.open_groups[idx])
+        else {
+            return true;
+        };
+        for option in 0..self.groups_with[rucksack].len() {
+            let group_idx = self.groups_with[rucksack][option];
+            if self.blocked[group_idx] > 0 {
+                continue;
+            }
+            self.set_assigned(group_idx, true);
+            self.groups.push(group_idx);
+            if self.fill_groups() {
+                return true;
+            }
+            self.groups.pop();
+            self.set_assigned(group_idx, false);
+        }
+        false
+    }
+
+    /// Assigns or unassigns the rucksacks in a group, and updates which candidate groups are still open.
+    fn set_assigned(&mut self, group_idx: usize, assigned: bool) {
+        for member_idx in 0..self.candidates[group_idx].len() {
+            let member = self.candidates[group_idx][member_idx];
+            self.assigned[member] = assigned;
+            for &other_group in &self.groups_with[member] {
+                let was_open = self.blocked[other_group] == 0;
+                if assigned {
+                    self.blocked[other_group] += 1;
+                } else {
+                    self.blocked[other_group] -= 1;
+                }
+                let is_open = self.blocked[other_group] == 0;
+                if was_open != is_open {
+                    for &other in &self.candidates[other_group] {
+                        if is_open {
+                            self.open_groups[other] += 1;
+                        } else {
+                            self.open_groups[other] -= 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[aoc(day3, part1, char_scan)]
+fn part1_char_scan(input: &str) -> u64 {
     input
         .lines()
         .map(|l| {
@@ -14,12 +263,11 @@ fn part1(input: &str) -> u64 {
         .sum()
 }
 
-#[aoc(day3, part2)]
-fn part2(input: &str) -> u64 {
+#[aoc(day3, part2, char_scan)]
+fn part2_char_scan(input: &str) -> u64 {
     input
         .lines()
         .tuples()
-        .into_iter()
         .map(|(a, b, c)| {
             let ab = common_chars(a, b);
             let ac = common_chars(a, c);
@@ -29,6 +277,104 @@ fn part2(input: &str) -> u64 {
         .sum()
 }
 
+/// Returns the priority of an item: 1 through 26 for `a` through `z` and 27 through 52 for `A` through `Z`.
+pub fn priority(item: char) -> Option<u64> {
+    match item {
+        'a'..='z' => Some(item as u64 - 'a' as u64 + 1),
+        'A'..='Z' => Some(item as u64 - 'A' as u64 + 27),
+        _ => None,
+    }
+}
+
+fn item_for_priority(priority: u64) -> char {
+    match priority {
+        1..=26 => (b'a' + priority as u8 - 1) as char,
+        27..=52 => (b'A' + priority as u8 - 27) as char,
+        _ => unreachable!("Invalid priority {priority}"),
+    }
+}
+
+/// The distinct items in a rucksack or compartment, as a bit mask where bit `p - 1` is set for the item with
+/// priority `p`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ItemSet(u64);
+
+impl ItemSet {
+    pub const EMPTY: ItemSet = ItemSet(0);
+    pub const ALL: ItemSet = ItemSet((1 << 52) - 1);
+
+    pub fn contains(self, item: char) -> bool {
+        priority(item).is_some_and(|priority| self.0 & 1 << (priority - 1) != 0)
+    }
+
+    pub fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The items that are in all sets. This is empty if there are no sets.
+    pub fn intersection_of(sets: impl IntoIterator<Item = ItemSet>) -> ItemSet {
+        let mut sets = sets.into_iter();
+        match sets.next() {
+            Some(first) => sets.fold(first, BitAnd::bitand),
+            None => ItemSet::EMPTY,
+        }
+    }
+
+    /// The items in the set, in order of priority.
+    pub fn items(self) -> impl Iterator<Item = char> {
+        self.priorities().map(item_for_priority)
+    }
+
+    /// The priorities of the items in the set, in ascending order.
+    pub fn priorities(self) -> impl Iterator<Item = u64> {
+        let mut bits = self.0;
+        std::iter::from_fn(move || {
+            (bits != 0).then(|| {
+                let priority = bits.trailing_zeros() as u64 + 1;
+                bits &= bits - 1;
+                priority
+            })
+        })
+    }
+
+    pub fn priority_sum(self) -> u64 {
+        self.priorities().sum()
+    }
+}
+
+impl FromStr for ItemSet {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bits = 0;
+        for item in s.chars() {
+            match priority(item) {
+                Some(priority) => bits |= 1 << (priority - 1),
+                None => bail!("'{item}' is not an item"),
+            }
+        }
+        Ok(ItemSet(bits))
+    }
+}
+
+impl BitAnd for ItemSet {
+    type Output = ItemSet;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        ItemSet(self.0 & rhs.0)
+    }
+}
+
+impl fmt::Display for ItemSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.items().try_for_each(|item| write!(f, "{item}"))
+    }
+}
+
 fn item_priority(item: char) -> u64 {
     const CHARS: [char; 52] = [
         'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v',
@@ -45,6 +391,7 @@ fn common_chars(a: &str, b: &str) -> Vec<char> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::next_random;
 
     const EXAMPLE_INPUT: &str = "\
     vJrwpWtwJgWrhcsFMMfFFhFp\n\
@@ -75,6 +422,132 @@ mod tests {
         assert_eq!(item_priority('t'), 20);
     }
 
+    #[test]
+    fn item_sets() {
+        let set = "vJrwpWtwJgWr".parse::<ItemSet>().unwrap();
+        assert_eq!(set.to_string(), "gprtvwJW");
+        assert_eq!(set.len(), 8);
+        assert!(set.contains('J') && !set.contains('j'));
+        let common = set & "hcsFMMfFFhFp".parse().unwrap();
+        assert_eq!(common.to_string(), "p");
+        assert_eq!(common.priority_sum(), 16);
+        assert_eq!(ItemSet::ALL.len(), 52);
+        assert_eq!(ItemSet::ALL.priority_sum(), (1..=52).sum::<u64>());
+        assert_eq!(ItemSet::intersection_of([]), ItemSet::EMPTY);
+        assert!("abc1".parse::<ItemSet>().is_err());
+    }
+
+    #[test]
+    fn priorities_match() {
+        for item in ('a'..='z').chain('A'..='Z') {
+            assert_eq!(super::priority(item), Some(item_priority(item)));
+            assert_eq!(item_for_priority(item_priority(item)), item);
+        }
+        assert_eq!(super::priority('1'), None);
+    }
+
+    #[test]
+    fn compartments_and_groups() {
+        let rucksacks = EXAMPLE_INPUT.lines().collect_vec();
+        let items = common_items(&rucksacks, 2).unwrap();
+        assert_eq!(items.iter().join(""), "pLPvts");
+        assert_eq!(
+            common_items(&["abcabcabc", "aBAbAb"], 3).unwrap().iter().join(","),
+            "abc,"
+        );
+        assert_eq!(common_items(&rucksacks, 1).unwrap()[0], rucksacks[0].parse().unwrap());
+        assert_eq!(group_common_items(&rucksacks, 3).unwrap().iter().join(","), "r,Z");
+        assert_eq!(badges(&rucksacks, 6).unwrap_err().to_string(), "group 1 has no badge");
+        assert_eq!(badges(&rucksacks, 3).unwrap(), ['r', 'Z']);
+        assert_eq!(
+            badges(&rucksacks, 1)
+                .unwrap_err()
+                .to_string()
+                .matches("multiple")
+                .count(),
+            6
+        );
+    }
+
+    #[test]
+    fn rucksack_errors() {
+        assert_eq!(
+            common_items(&["abab", "abcab"], 2).unwrap_err().to_string(),
+            "Rucksack 2 has 5 items, which can't be split into 2 equal compartments"
+        );
+        assert_eq!(
+            format!("{:#}", common_items(&["ab", "a1"], 2).unwrap_err()),
+            "Invalid rucksack 2: '1' is not an item"
+        );
+        assert_eq!(
+            group_common_items(&["ab", "ab"], 3).unwrap_err().to_string(),
+            "2 rucksacks can't be split into groups of 3"
+        );
+        assert_eq!(
+            badges(&["ab", "cd", "ab", "ab"], 2).unwrap_err().to_string(),
+            "group 1 has no badge, group 2 has multiple badge candidates ab"
+        );
+        assert_eq!(
+            badge_issues(&["".parse().unwrap(), "x".parse().unwrap()]),
+            [BadgeIssue {
+                group: 1,
+                kind: BadgeIssueKind::NoBadge
+            }]
+        );
+    }
+
+    fn check_badge_groups(rucksacks: &[ItemSet], groups: &[Vec<usize>], group_size: usize) {
+        assert!(groups.iter().all(|group| group.len() == group_size));
+        assert!(groups
+            .iter()
+            .all(|group| ItemSet::intersection_of(group.iter().map(|&idx| rucksacks[idx])).len() == 1));
+        let mut all = groups.concat();
+        all.sort_unstable();
+        assert_eq!(all, (0..rucksacks.len()).collect_vec());
+    }
+
+    #[test]
+    fn badge_groups_example() {
+        let rucksacks = EXAMPLE_INPUT.lines().map(|l| l.parse().unwrap()).collect_vec();
+        let groups = find_badge_groups(&rucksacks, 3).unwrap();
+        check_badge_groups(&rucksacks, &groups, 3);
+
+        let shuffled = [4, 0, 3, 1, 5, 2].map(|idx| rucksacks[idx]);
+        let groups = find_badge_groups(&shuffled, 3).unwrap();
+        check_badge_groups(&shuffled, &groups, 3);
+    }
+
+    #[test]
+    fn badge_groups_impossible() {
+        let rucksacks = ["ab", "ab", "ab", "cd", "ce", "xy"].map(|l| l.parse().unwrap());
+        assert_eq!(find_badge_groups(&rucksacks, 3), None);
+        assert_eq!(find_badge_groups(&rucksacks[..4], 3), None);
+        assert_eq!(find_badge_groups(&rucksacks[..4], 2), None);
+
+        let chain = ["ab", "bc", "cd", "de"].map(|l| l.parse().unwrap());
+        let mut groups = find_badge_groups(&chain, 2).unwrap();
+        groups.sort();
+        assert_eq!(groups, [[0, 1], [2, 3]]);
+    }
+
+    #[test]
+    fn badge_groups_shuffled_puzzle() {
+        let mut rucksacks = PUZZLE_INPUT.lines().map(|l| l.parse().unwrap()).collect_vec();
+        // Deterministic shuffle
+        let mut state = 3u64;
+        for idx in (1..rucksacks.len()).rev() {
+            rucksacks.swap(idx, next_random(&mut state, idx as u64 + 1) as usize);
+        }
+        let groups = find_badge_groups(&rucksacks, 3).unwrap();
+        check_badge_groups(&rucksacks, &groups, 3);
+    }
+
+    #[test]
+    fn char_scan_solutions() {
+        assert_eq!(part1_char_scan(PUZZLE_INPUT), 8105);
+        assert_eq!(part2_char_scan(PUZZLE_INPUT), 2363);
+    }
+
     #[test]
     fn part1_solution() {
         assert_eq!(part1(PUZZLE_INPUT), 8105);
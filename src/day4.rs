@@ -1,61 +1,138 @@
-use std::{
-    cmp::{max, min},
-    str::FromStr,
-};
-
+use anyhow::{bail, Context};
 use aoc_runner_derive::aoc;
 
+use crate::interval::{Interval, IntervalSet, ReversedRanges};
+
 #[aoc(day4, part1)]
 fn part1(input: &str) -> u64 {
-    input
-        .lines()
-        .map(|pair| {
-            let (one, two) = pair.split_once(',').unwrap();
-            let one = one.parse().unwrap();
-            let two = two.parse().unwrap();
-            does_range_include_other(&one, &two)
+    parse_groups(input, ReversedRanges::Reject)
+        .unwrap()
+        .iter()
+        .filter(|group| {
+            any_pair(group, |one, two| {
+                one.contains_interval(two) || two.contains_interval(one)
+            })
         })
-        .filter(|f| *f)
         .count() as u64
 }
 
 #[aoc(day4, part2)]
 fn part2(input: &str) -> u64 {
+    parse_groups(input, ReversedRanges::Reject)
+        .unwrap()
+        .iter()
+        .filter(|group| any_pair(group, Interval::overlaps))
+        .count() as u64
+}
+
+/// Whether `predicate` holds for any two different assignments in the group.
+fn any_pair(group: &[Interval], predicate: impl Fn(&Interval, &Interval) -> bool) -> bool {
+    group
+        .iter()
+        .enumerate()
+        .any(|(idx, one)| group[idx + 1..].iter().any(|two| predicate(one, two)))
+}
+
+/// Parses the assignments on each line. A line has two or more comma-separated ranges, with optional whitespace
+/// around the numbers. Blank lines are skipped.
+pub fn parse_groups(input: &str, reversed: ReversedRanges) -> anyhow::Result<Vec<Vec<Interval>>> {
     input
         .lines()
-        .map(|pair| {
-            let (one, two) = pair.split_once(',').unwrap();
-            let one = one.parse().unwrap();
-            let two = two.parse().unwrap();
-            do_ranges_overlap(&one, &two)
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(line_idx, line)| {
+            parse_group(line, reversed).with_context(|| format!("Invalid assignments on line {}", line_idx + 1))
         })
-        .filter(|f| *f)
-        .count() as u64
+        .collect()
 }
 
-fn does_range_include_other(a: &Range, b: &Range) -> bool {
-    (a.min <= b.min && a.max >= b.max) || (b.min <= a.min && b.max >= a.max)
+fn parse_group(line: &str, reversed: ReversedRanges) -> anyhow::Result<Vec<Interval>> {
+    let group = line
+        .split(',')
+        .map(|range| Interval::parse(range, reversed))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if group.len() < 2 {
+        bail!("expected at least two ranges separated by ','");
+    }
+    Ok(group)
 }
 
-fn do_ranges_overlap(a: &Range, b: &Range) -> bool {
-    max(a.min, b.min) <= min(a.max, b.max)
+/// Parses the assignments of all elves, in the order they appear in the input.
+pub fn parse_assignments(input: &str) -> anyhow::Result<Vec<Interval>> {
+    Ok(parse_groups(input, ReversedRanges::Reject)?.concat())
 }
 
-struct Range {
-    min: u64,
-    max: u64,
+/// How the assignments of all elves overlap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageAnalysis {
+    /// The largest number of elves assigned to the same section.
+    pub max_overlap: usize,
+    /// The sections that `max_overlap` elves are assigned to.
+    pub most_covered: IntervalSet,
+    /// Indices of the elves whose sections are all covered by other elves.
+    pub redundant_elves: Vec<usize>,
 }
 
-impl FromStr for Range {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (min, max) = s.split_once('-').unwrap();
-        Ok(Self {
-            min: min.parse().unwrap(),
-            max: max.parse().unwrap(),
+/// Analyzes the overlap between all assignments with a sweep over their start and end points.
+pub fn analyze_coverage(assignments: &[Interval]) -> CoverageAnalysis {
+    let profile = coverage_profile(assignments);
+    let max_overlap = profile.iter().map(|&(_, count)| count).max().unwrap_or(0);
+    let most_covered = profile
+        .iter()
+        .filter(|&&(_, count)| count == max_overlap)
+        .map(|&(interval, _)| interval)
+        .collect();
+    // An elf is redundant if none of its sections are covered by that elf alone
+    let covered_once = profile
+        .iter()
+        .filter(|&&(_, count)| count == 1)
+        .map(|&(interval, _)| interval)
+        .collect::<Vec<_>>();
+    let redundant_elves = assignments
+        .iter()
+        .enumerate()
+        .filter(|(_, assignment)| {
+            let idx = covered_once.partition_point(|interval| interval.end < assignment.start);
+            covered_once
+                .get(idx)
+                .is_none_or(|interval| !interval.overlaps(assignment))
         })
+        .map(|(elf, _)| elf)
+        .collect();
+    CoverageAnalysis {
+        max_overlap,
+        most_covered,
+        redundant_elves,
+    }
+}
+
+/// Splits the covered sections into intervals with the number of elves assigned to each section, in ascending
+/// order. Sections that no elf is assigned to are left out.
+pub fn coverage_profile(assignments: &[Interval]) -> Vec<(Interval, usize)> {
+    let mut events = Vec::with_capacity(assignments.len() * 2);
+    for assignment in assignments {
+        events.push((assignment.start, 1));
+        // An assignment that ends at the last section never ends
+        if let Some(after_end) = assignment.end.checked_add(1) {
+            events.push((after_end, -1));
+        }
+    }
+    events.sort_unstable();
+
+    let mut profile = Vec::new();
+    let mut count = 0isize;
+    let mut previous = 0;
+    for (position, delta) in events {
+        if position > previous && count > 0 {
+            profile.push((Interval::new(previous, position - 1), count as usize));
+        }
+        previous = position;
+        count += delta;
+    }
+    if count > 0 {
+        profile.push((Interval::new(previous, u64::MAX), count as usize));
     }
+    profile
 }
 
 #[cfg(test)]
@@ -86,4 +163,107 @@ mod tests {
     fn part2_example() {
         assert_eq!(part2(EXAMPLE_INPUT), 4);
     }
+
+    /// Coverage analysis that checks every section one by one.
+    fn analyze_coverage_brute_force(assignments: &[Interval]) -> CoverageAnalysis {
+        let sections =
+            assignments.iter().map(|a| a.start).min().unwrap()..=assignments.iter().map(|a| a.end).max().unwrap();
+        let elves_at = |section| assignments.iter().filter(|a| a.contains(section)).count();
+        let max_overlap = sections.clone().map(elves_at).max().unwrap();
+        let most_covered = sections
+            .filter(|&section| elves_at(section) == max_overlap)
+            .map(|section| Interval::new(section, section))
+            .collect();
+        let redundant_elves = (0..assignments.len())
+            .filter(|&elf| {
+                (assignments[elf].start..=assignments[elf].end).all(|section| {
+                    assignments
+                        .iter()
+                        .enumerate()
+                        .any(|(other, a)| other != elf && a.contains(section))
+                })
+            })
+            .collect();
+        CoverageAnalysis {
+            max_overlap,
+            most_covered,
+            redundant_elves,
+        }
+    }
+
+    #[test]
+    fn coverage_example() {
+        let assignments = parse_assignments(EXAMPLE_INPUT).unwrap();
+        let analysis = analyze_coverage(&assignments);
+        assert_eq!(analysis.max_overlap, 8);
+        assert_eq!(analysis.most_covered.to_string(), "6-6");
+        assert_eq!(analysis.redundant_elves, [0, 1, 2, 3, 4, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(analysis, analyze_coverage_brute_force(&assignments));
+    }
+
+    #[test]
+    fn coverage_puzzle() {
+        let assignments = parse_assignments(PUZZLE_INPUT).unwrap();
+        assert_eq!(
+            analyze_coverage(&assignments),
+            analyze_coverage_brute_force(&assignments)
+        );
+    }
+
+    #[test]
+    fn coverage_profile_edges() {
+        let assignments = [Interval::new(1, 3), Interval::new(4, 4), Interval::new(8, u64::MAX)];
+        assert_eq!(
+            coverage_profile(&assignments),
+            [
+                (Interval::new(1, 3), 1),
+                (Interval::new(4, 4), 1),
+                (Interval::new(8, u64::MAX), 1)
+            ]
+        );
+        let analysis = analyze_coverage(&assignments);
+        assert_eq!(analysis.max_overlap, 1);
+        assert_eq!(analysis.most_covered.to_string(), format!("1-4,8-{}", u64::MAX));
+        assert_eq!(analysis.most_covered.coverage(), 4 + u128::from(u64::MAX) - 7);
+        assert!(analysis.redundant_elves.is_empty());
+
+        let duplicates = [Interval::new(2, 5), Interval::new(2, 5), Interval::new(1, 1)];
+        assert_eq!(analyze_coverage(&duplicates).redundant_elves, [0, 1]);
+        assert_eq!(analyze_coverage(&[]).max_overlap, 0);
+    }
+
+    #[test]
+    fn parse_flexible_input() {
+        let input = " 2 - 4 , 6-8 \n\n8-6,1-1,3-3\n";
+        assert!(parse_groups(input, ReversedRanges::Reject).is_err());
+        let groups = parse_groups(input, ReversedRanges::Normalize).unwrap();
+        assert_eq!(
+            groups,
+            [
+                vec![Interval::new(2, 4), Interval::new(6, 8)],
+                vec![Interval::new(6, 8), Interval::new(1, 1), Interval::new(3, 3)]
+            ]
+        );
+        assert_eq!(part1(input.replace("8-6", "6-8").as_str()), 0);
+        assert_eq!(part2("1-2,5-6,2-3\n1-2,4-5,7-8"), 1);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |input: &str| format!("{:#}", parse_groups(input, ReversedRanges::Reject).unwrap_err());
+        assert_eq!(
+            error("2-4,6-8\n2-3,5-4"),
+            "Invalid assignments on line 2: range start 5 is greater than end 4"
+        );
+        assert_eq!(
+            error("2-4,6-8\n2-3,4-5\n2-3"),
+            "Invalid assignments on line 3: expected at least two ranges separated by ','"
+        );
+        assert_eq!(
+            error("2-4,6"),
+            "Invalid assignments on line 1: '6' is not a range like '2-4'"
+        );
+        assert!(error("2-4,x-8").starts_with("Invalid assignments on line 1: invalid range start 'x'"));
+        assert!(error("2-4,6-").starts_with("Invalid assignments on line 1: invalid range end ''"));
+    }
 }
@@ -1,104 +1,638 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{self, Display},
+    ops::{Deref, DerefMut},
+    rc::Rc,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
 use aoc_runner_derive::{aoc, aoc_generator};
+use itertools::{Either, Itertools};
 use parse_display::{Display, FromStr};
 
 #[aoc_generator(day5)]
-fn parse_input(input: &str) -> (Vec<Stack>, Vec<Move>) {
+pub fn parse_input(input: &str) -> (Stacks, Vec<Move>) {
     let (stacks, moves) = input.split_once("\n\n").unwrap();
 
-    // Parse initial stacks
-    let mut initial_stacks = Vec::new();
-    for line in stacks.lines().rev().skip(1) {
-        for (i, c) in line.chars().enumerate() {
-            if c.is_alphabetic() {
-                let stack_idx = i / 4;
-                if initial_stacks.get(stack_idx).is_none() {
-                    initial_stacks.push(Vec::new());
-                }
-                if let Some(stack) = initial_stacks.get_mut(stack_idx) {
-                    stack.push(c);
-                }
-            }
-        }
-    }
-
-    // Parse moves
+    let stacks = stacks.parse::<Stacks>().unwrap();
     let moves = moves
         .lines()
         .map(|line| line.parse::<Move>().unwrap())
         .collect::<Vec<_>>();
 
-    (initial_stacks, moves)
+    (stacks, moves)
 }
 
 #[aoc(day5, part1)]
-fn part1(input: &(Vec<Stack>, Vec<Move>)) -> String {
+fn part1(input: &(Stacks, Vec<Move>)) -> anyhow::Result<String> {
     let (stacks, moves) = input;
-    let mut stacks = stacks.clone();
-
-    for move_ in moves {
-        move_crates_one_by_one(&mut stacks, move_);
-    }
+    let stacks = final_stacks(&CrateMover9000, stacks, moves)?;
+    Ok(stacks.top_crates(&EmptyStacks::Skip))
+}
 
-    get_top_crates(&stacks)
+#[aoc(day5, part1, chunked)]
+fn part1_chunked(input: &(Stacks, Vec<Move>)) -> anyhow::Result<String> {
+    let (stacks, moves) = input;
+    let stacks = final_stacks_chunked(&CrateMover9000, stacks, moves)?;
+    Ok(stacks.top_crates(&EmptyStacks::Skip))
 }
 
 #[aoc(day5, part2)]
-fn part2(input: &(Vec<Stack>, Vec<Move>)) -> String {
+fn part2(input: &(Stacks, Vec<Move>)) -> anyhow::Result<String> {
     let (stacks, moves) = input;
-    let mut stacks = stacks.clone();
+    let stacks = final_stacks(&CrateMover9001, stacks, moves)?;
+    Ok(stacks.top_crates(&EmptyStacks::Skip))
+}
+
+#[aoc(day5, part2, chunked)]
+fn part2_chunked(input: &(Stacks, Vec<Move>)) -> anyhow::Result<String> {
+    let (stacks, moves) = input;
+    let stacks = final_stacks_chunked(&CrateMover9001, stacks, moves)?;
+    Ok(stacks.top_crates(&EmptyStacks::Skip))
+}
+
+pub type Stack = Vec<Crate>;
+pub type Crate = String;
+
+/// The stacks of crates, from left to right with the bottom crate of each stack first.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Stacks(pub Vec<Stack>);
+
+impl Deref for Stacks {
+    type Target = Vec<Stack>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// How empty stacks are reported when listing the top crates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmptyStacks {
+    /// Leave empty stacks out.
+    Skip,
+    /// Use the given text for empty stacks.
+    Placeholder(String),
+}
+
+/// Where a crate is, with the stack numbered from 1 like in the moves and the level counted from the floor,
+/// starting at 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrateLocation {
+    pub stack: usize,
+    pub level: usize,
+}
+
+impl Stacks {
+    /// Returns the top crate of every stack, or `None` for empty stacks.
+    pub fn tops(&self) -> Vec<Option<&str>> {
+        self.iter().map(|stack| stack.last().map(String::as_str)).collect()
+    }
+
+    /// Returns the labels of the top crates joined together, handling empty stacks as configured.
+    pub fn top_crates(&self, empty_stacks: &EmptyStacks) -> String {
+        self.tops()
+            .into_iter()
+            .filter_map(|top| match (top, empty_stacks) {
+                (Some(top), _) => Some(top),
+                (None, EmptyStacks::Skip) => None,
+                (None, EmptyStacks::Placeholder(placeholder)) => Some(placeholder.as_str()),
+            })
+            .collect()
+    }
+
+    /// Returns the number of crates on every stack.
+    pub fn heights(&self) -> Vec<usize> {
+        self.iter().map(Vec::len).collect()
+    }
+
+    /// Returns the locations of all crates with the given label, from the first stack to the last and bottom to top.
+    pub fn locate(&self, label: &str) -> Vec<CrateLocation> {
+        self.iter()
+            .enumerate()
+            .flat_map(|(stack_idx, stack)| {
+                stack
+                    .iter()
+                    .positions(move |crate_| crate_ == label)
+                    .map(move |level| CrateLocation {
+                        stack: stack_idx + 1,
+                        level,
+                    })
+            })
+            .collect()
+    }
+}
+
+impl DerefMut for Stacks {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl FromStr for Stacks {
+    type Err = anyhow::Error;
+
+    /// Parses the drawing of the stacks. The footer has to number the stacks from 1, and every crate has to be
+    /// above a stack number and on top of another crate or the floor. Crate labels can be longer than one
+    /// character as long as they don't contain whitespace.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().collect_vec();
+        while lines.last().is_some_and(|(_, line)| line.trim().is_empty()) {
+            lines.pop();
+        }
+        let (_, footer) = lines.pop().context("Drawing is empty")?;
+
+        let stack_numbers = tokens(footer);
+        for (stack_idx, (_, _, number)) in stack_numbers.iter().enumerate() {
+            if number.parse() != Ok(stack_idx + 1) {
+                bail!(
+                    "Footer should number the stacks from 1, found '{number}' for stack {}",
+                    stack_idx + 1
+                );
+            }
+        }
+
+        let mut stacks = vec![Stack::new(); stack_numbers.len()];
+        for (level, (line_idx, line)) in lines.into_iter().rev().enumerate() {
+            let line_number = line_idx + 1;
+            for (start, end, token) in tokens(line) {
+                let label = token
+                    .strip_prefix('[')
+                    .and_then(|token| token.strip_suffix(']'))
+                    .filter(|label| !label.is_empty())
+                    .with_context(|| format!("'{token}' on line {line_number} is not a crate"))?;
+                let stack_idx = stack_numbers
+                    .iter()
+                    .position(|(number_start, number_end, _)| *number_start <= end && start <= *number_end)
+                    .with_context(|| format!("Crate '{token}' on line {line_number} is not above a stack number"))?;
+                let stack = &mut stacks[stack_idx];
+                if stack.len() != level {
+                    bail!("Crate '{token}' on line {line_number} is floating above an empty spot");
+                }
+                stack.push(label.to_string());
+            }
+        }
 
-    for move_ in moves {
-        move_crates_all_at_once(&mut stacks, move_);
+        Ok(Stacks(stacks))
     }
+}
 
-    get_top_crates(&stacks)
+/// Returns the whitespace-separated tokens of `line` with the character positions of their first and last
+/// characters.
+fn tokens(line: &str) -> Vec<(usize, usize, String)> {
+    line.chars()
+        .enumerate()
+        .group_by(|(_, c)| c.is_whitespace())
+        .into_iter()
+        .filter(|(is_whitespace, _)| !is_whitespace)
+        .map(|(_, chars)| {
+            let chars = chars.collect_vec();
+            let token = chars.iter().map(|(_, c)| c).collect();
+            (chars[0].0, chars[chars.len() - 1].0, token)
+        })
+        .collect()
 }
 
-type Stack = Vec<Crate>;
-type Crate = char;
+impl Display for Stacks {
+    /// Draws the stacks in the same format as the puzzle input. All columns get the width of the widest crate.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label_width = self
+            .iter()
+            .flatten()
+            .map(|label| label.chars().count())
+            .max()
+            .unwrap_or(1);
+        let number_width = self.len().to_string().len();
+        let width = (label_width + 2).max(number_width);
+        let height = self.iter().map(Vec::len).max().unwrap_or(0);
 
-fn move_crates_one_by_one<'a>(stacks: &'a mut Vec<Stack>, move_: &'a Move) -> &'a mut Vec<Stack> {
-    let (amount, from, to) = (move_.amount as usize, move_.from as usize, move_.to as usize);
+        for level in (0..height).rev() {
+            let line = self
+                .iter()
+                .map(|stack| match stack.get(level) {
+                    Some(label) => format!("{:<width$}", format!("[{label}]")),
+                    None => " ".repeat(width),
+                })
+                .join(" ");
+            writeln!(f, "{line}")?;
+        }
 
-    for _ in 0..amount {
-        let crate_ = stacks.get_mut(from - 1).unwrap().pop().unwrap();
-        stacks.get_mut(to - 1).unwrap().push(crate_);
+        let footer = (1..=self.len())
+            .map(|number| {
+                let number = number.to_string();
+                let padding = (width - number.len()).div_ceil(2);
+                format!("{:<width$}", format!("{}{number}", " ".repeat(padding)))
+            })
+            .join(" ");
+        write!(f, "{footer}")
     }
-    stacks
 }
 
-fn move_crates_all_at_once<'a>(stacks: &'a mut Vec<Stack>, move_: &'a Move) -> &'a mut Vec<Stack> {
-    let (amount, from, to) = (move_.amount as usize, move_.from as usize, move_.to as usize);
+/// A crane that can carry out the rearrangement procedure.
+pub trait Crane {
+    /// Moves crates as described by `move_`. If the move is not possible the stacks are left untouched.
+    fn apply(&self, stacks: &mut [Stack], move_: &Move) -> Result<(), MoveError>;
+
+    /// Same as [`Crane::apply`], but on chunked stacks. The work done depends on the number of chunks involved
+    /// instead of the number of crates. Every move can split one chunk, so after `m` moves a single move can still
+    /// touch `O(m)` chunks in the worst case.
+    fn apply_chunked(&self, stacks: &mut [ChunkedStack], move_: &Move) -> Result<(), MoveError>;
+}
+
+/// Moves one crate at a time.
+pub struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn apply(&self, stacks: &mut [Stack], move_: &Move) -> Result<(), MoveError> {
+        let (from, to) = check_move(stacks, Vec::len, move_)?;
+        for _ in 0..move_.amount {
+            let crate_ = stacks[from].pop().unwrap();
+            stacks[to].push(crate_);
+        }
+        Ok(())
+    }
 
-    let from_len = stacks.get(from - 1).unwrap().len();
+    fn apply_chunked(&self, stacks: &mut [ChunkedStack], move_: &Move) -> Result<(), MoveError> {
+        let (from, to) = check_move(stacks, ChunkedStack::len, move_)?;
+        if from == to {
+            // Moving crates one at a time onto the same stack leaves it unchanged
+            return Ok(());
+        }
+        let mut crates = stacks[from].take_top(move_.amount);
+        reverse_segments(&mut crates);
+        stacks[to].put(crates);
+        Ok(())
+    }
+}
 
-    let mut crates_to_move = stacks.get_mut(from - 1).unwrap().split_off(from_len - amount);
+/// Moves all crates at once, keeping their order.
+pub struct CrateMover9001;
 
-    stacks.get_mut(to - 1).unwrap().append(&mut crates_to_move);
+impl Crane for CrateMover9001 {
+    fn apply(&self, stacks: &mut [Stack], move_: &Move) -> Result<(), MoveError> {
+        let (from, to) = check_move(stacks, Vec::len, move_)?;
+        move_batch(stacks, from, to, move_.amount);
+        Ok(())
+    }
 
-    stacks
+    fn apply_chunked(&self, stacks: &mut [ChunkedStack], move_: &Move) -> Result<(), MoveError> {
+        let (from, to) = check_move(stacks, ChunkedStack::len, move_)?;
+        let crates = stacks[from].take_top(move_.amount);
+        stacks[to].put(crates);
+        Ok(())
+    }
 }
 
-fn get_top_crates(stacks: &[Stack]) -> String {
-    stacks
-        .to_owned()
-        .iter()
-        .map(|c| c.last().unwrap().to_owned())
-        .collect::<String>()
+/// Moves crates in batches of at most `capacity` crates, keeping the order within a batch. A capacity of 1 behaves
+/// like the [`CrateMover9000`] and a capacity of at least the largest amount like the [`CrateMover9001`].
+pub struct BatchCrane {
+    capacity: usize,
 }
 
-#[derive(Display, FromStr, PartialEq, Debug)]
+impl BatchCrane {
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Crane capacity must be at least 1");
+        BatchCrane { capacity }
+    }
+}
+
+impl Crane for BatchCrane {
+    fn apply(&self, stacks: &mut [Stack], move_: &Move) -> Result<(), MoveError> {
+        let (from, to) = check_move(stacks, Vec::len, move_)?;
+        let mut remaining = move_.amount;
+        while remaining > 0 {
+            let batch = remaining.min(self.capacity);
+            move_batch(stacks, from, to, batch);
+            remaining -= batch;
+        }
+        Ok(())
+    }
+
+    fn apply_chunked(&self, stacks: &mut [ChunkedStack], move_: &Move) -> Result<(), MoveError> {
+        let (from, to) = check_move(stacks, ChunkedStack::len, move_)?;
+        let mut remaining = move_.amount;
+        while remaining > 0 {
+            let batch = remaining.min(self.capacity);
+            let crates = stacks[from].take_top(batch);
+            stacks[to].put(crates);
+            remaining -= batch;
+        }
+        Ok(())
+    }
+}
+
+/// A stack stored as a list of chunks that share their crates with other stacks, so that moving crates only splits
+/// at most one chunk instead of copying every crate. Chunks that end up next to each other in their original order
+/// are merged again.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkedStack {
+    /// From bottom to top.
+    segments: Vec<Segment>,
+    len: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    crates: Rc<[Crate]>,
+    start: usize,
+    end: usize,
+    /// Whether `crates[start..end]` is stacked top to bottom instead of bottom to top.
+    reversed: bool,
+}
+
+impl Segment {
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    fn top(&self) -> &Crate {
+        if self.reversed {
+            &self.crates[self.start]
+        } else {
+            &self.crates[self.end - 1]
+        }
+    }
+
+    /// Iterates from bottom to top.
+    fn iter(&self) -> impl Iterator<Item = &Crate> {
+        let crates = self.crates[self.start..self.end].iter();
+        if self.reversed {
+            Either::Left(crates.rev())
+        } else {
+            Either::Right(crates)
+        }
+    }
+
+    /// Whether `other` stacked on top of this segment continues the same run of crates, so that the two can be
+    /// merged. This happens when crates that were split off are put back on the stack they came from.
+    fn continues_with(&self, other: &Segment) -> bool {
+        Rc::ptr_eq(&self.crates, &other.crates)
+            && self.reversed == other.reversed
+            && if self.reversed {
+                other.end == self.start
+            } else {
+                other.start == self.end
+            }
+    }
+
+    fn merge(&mut self, other: Segment) {
+        if self.reversed {
+            self.start = other.start;
+        } else {
+            self.end = other.end;
+        }
+    }
+
+    /// Splits off the top `amount` crates into a new segment.
+    fn split_top(&mut self, amount: usize) -> Segment {
+        let mut top = self.clone();
+        if self.reversed {
+            top.end = self.start + amount;
+            self.start += amount;
+        } else {
+            top.start = self.end - amount;
+            self.end -= amount;
+        }
+        top
+    }
+}
+
+impl ChunkedStack {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn top(&self) -> Option<&Crate> {
+        self.segments.last().map(Segment::top)
+    }
+
+    /// Iterates from bottom to top.
+    pub fn iter(&self) -> impl Iterator<Item = &Crate> {
+        self.segments.iter().flat_map(Segment::iter)
+    }
+
+    /// Number of chunks the stack is made up of.
+    pub fn n_chunks(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Removes the top `amount` crates and returns them from bottom to top. The caller checks that there are
+    /// enough crates.
+    fn take_top(&mut self, amount: usize) -> Vec<Segment> {
+        let mut taken = Vec::new();
+        let mut remaining = amount;
+        while remaining > 0 {
+            let segment = self.segments.last_mut().unwrap();
+            if segment.len() <= remaining {
+                remaining -= segment.len();
+                taken.push(self.segments.pop().unwrap());
+            } else {
+                taken.push(segment.split_top(remaining));
+                remaining = 0;
+            }
+        }
+        self.len -= amount;
+        taken.reverse();
+        taken
+    }
+
+    fn put(&mut self, segments: Vec<Segment>) {
+        for segment in segments {
+            self.len += segment.len();
+            match self.segments.last_mut() {
+                Some(top) if top.continues_with(&segment) => top.merge(segment),
+                _ => self.segments.push(segment),
+            }
+        }
+    }
+}
+
+/// Turns a list of segments upside down without touching the crates.
+fn reverse_segments(segments: &mut [Segment]) {
+    segments.reverse();
+    for segment in segments {
+        segment.reversed = !segment.reversed;
+    }
+}
+
+impl From<&Stack> for ChunkedStack {
+    fn from(stack: &Stack) -> Self {
+        let mut chunked = ChunkedStack::default();
+        if !stack.is_empty() {
+            chunked.put(vec![Segment {
+                crates: Rc::from(stack.as_slice()),
+                start: 0,
+                end: stack.len(),
+                reversed: false,
+            }]);
+        }
+        chunked
+    }
+}
+
+impl From<&ChunkedStack> for Stack {
+    fn from(stack: &ChunkedStack) -> Self {
+        stack.iter().cloned().collect()
+    }
+}
+
+/// Checks that both stacks exist and that there are enough crates to move, and returns the indices of the stacks.
+fn check_move<T>(stacks: &[T], len: impl Fn(&T) -> usize, move_: &Move) -> Result<(usize, usize), MoveError> {
+    let stack_idx = |stack: usize| match stack.checked_sub(1) {
+        Some(idx) if idx < stacks.len() => Ok(idx),
+        _ => Err(MoveError::NonexistentStack { stack }),
+    };
+    let (from, to) = (stack_idx(move_.from)?, stack_idx(move_.to)?);
+    let available = len(&stacks[from]);
+    if available < move_.amount {
+        return Err(MoveError::NotEnoughCrates {
+            stack: move_.from,
+            available,
+            requested: move_.amount,
+        });
+    }
+    Ok((from, to))
+}
+
+fn move_batch(stacks: &mut [Stack], from: usize, to: usize, amount: usize) {
+    let from_len = stacks[from].len();
+    let mut crates_to_move = stacks[from].split_off(from_len - amount);
+    stacks[to].append(&mut crates_to_move);
+}
+
+#[derive(Display, Debug, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    #[display("stack {stack} does not exist")]
+    NonexistentStack { stack: usize },
+    #[display("stack {stack} has {available} crates, can't move {requested}")]
+    NotEnoughCrates {
+        stack: usize,
+        available: usize,
+        requested: usize,
+    },
+}
+
+impl std::error::Error for MoveError {}
+
+/// A move that could not be carried out, with the index of the move in the list.
+#[derive(Display, Debug, Clone, PartialEq, Eq)]
+#[display("move {move_idx} failed: {error}")]
+pub struct SimulationError {
+    pub move_idx: usize,
+    pub error: MoveError,
+}
+
+impl std::error::Error for SimulationError {}
+
+/// Carries out all moves with `crane` and returns the stacks before the first move and after every move.
+pub fn simulate(crane: &dyn Crane, stacks: &Stacks, moves: &[Move]) -> Result<Vec<Stacks>, SimulationError> {
+    let mut history = vec![stacks.clone()];
+    let mut stacks = stacks.clone();
+    for (move_idx, move_) in moves.iter().enumerate() {
+        crane
+            .apply(&mut stacks, move_)
+            .map_err(|error| SimulationError { move_idx, error })?;
+        history.push(stacks.clone());
+    }
+    Ok(history)
+}
+
+/// Carries out all moves with `crane` on chunked stacks and returns the final stacks. This is much faster than
+/// [`final_stacks`] when large amounts of crates are moved.
+pub fn final_stacks_chunked(crane: &dyn Crane, stacks: &Stacks, moves: &[Move]) -> Result<Stacks, SimulationError> {
+    let mut chunked = stacks.iter().map(ChunkedStack::from).collect_vec();
+    for (move_idx, move_) in moves.iter().enumerate() {
+        crane
+            .apply_chunked(&mut chunked, move_)
+            .map_err(|error| SimulationError { move_idx, error })?;
+    }
+    Ok(Stacks(chunked.iter().map(Stack::from).collect()))
+}
+
+/// Carries out all moves with `crane` and returns the final stacks.
+pub fn final_stacks(crane: &dyn Crane, stacks: &Stacks, moves: &[Move]) -> Result<Stacks, SimulationError> {
+    let mut stacks = stacks.clone();
+    for (move_idx, move_) in moves.iter().enumerate() {
+        crane
+            .apply(&mut stacks, move_)
+            .map_err(|error| SimulationError { move_idx, error })?;
+    }
+    Ok(stacks)
+}
+
+/// Searches for a shortest list of moves that turns `start` into `target` with `crane`, using breadth-first
+/// search over the possible stack states. Returns `None` if no list of at most `max_moves` moves exists, which
+/// is known right away if the stacks don't hold the same crates.
+pub fn solve(crane: &dyn Crane, start: &Stacks, target: &Stacks, max_moves: usize) -> Option<Vec<Move>> {
+    let sorted_crates = |stacks: &Stacks| stacks.iter().flatten().cloned().sorted().collect_vec();
+    if start.len() != target.len() || sorted_crates(start) != sorted_crates(target) {
+        return None;
+    }
+
+    let mut previous: HashMap<Stacks, Option<(Stacks, Move)>> = HashMap::from([(start.clone(), None)]);
+    let mut queue = VecDeque::from([(start.clone(), 0)]);
+    while let Some((stacks, n_moves)) = queue.pop_front() {
+        if &stacks == target {
+            let mut moves = Vec::new();
+            let mut current = &stacks;
+            while let Some((prev, move_)) = &previous[current] {
+                moves.push(move_.clone());
+                current = prev;
+            }
+            moves.reverse();
+            return Some(moves);
+        }
+        if n_moves == max_moves {
+            continue;
+        }
+
+        for (from, to) in (0..stacks.len()).cartesian_product(0..stacks.len()) {
+            if from == to {
+                continue;
+            }
+            for amount in 1..=stacks[from].len() {
+                let move_ = Move {
+                    amount,
+                    from: from + 1,
+                    to: to + 1,
+                };
+                let mut next = stacks.clone();
+                crane.apply(&mut next, &move_).unwrap();
+                if !previous.contains_key(&next) {
+                    previous.insert(next.clone(), Some((stacks.clone(), move_)));
+                    queue.push_back((next, n_moves + 1));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Formats moves one per line, the way they appear in the puzzle input.
+pub fn format_moves(moves: &[Move]) -> String {
+    moves.iter().map(|move_| format!("{move_}\n")).collect()
+}
+
+#[derive(Display, FromStr, PartialEq, Eq, Debug, Clone)]
 #[display("move {amount} from {from} to {to}")]
-struct Move {
-    amount: u32,
-    from: u32,
-    to: u32,
+pub struct Move {
+    pub amount: usize,
+    pub from: usize,
+    pub to: usize,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::next_random;
     use indoc::indoc;
+    use std::time::Instant;
 
     const EXAMPLE_INPUT: &str = indoc! {"
         [D]    
@@ -114,14 +648,19 @@ mod tests {
 
     const PUZZLE_INPUT: &str = include_str!("../input/2022/day5.txt");
 
+    fn stacks(stacks: &[&[&str]]) -> Stacks {
+        Stacks(
+            stacks
+                .iter()
+                .map(|stack| stack.iter().map(|label| label.to_string()).collect())
+                .collect(),
+        )
+    }
+
     #[test]
     fn parse_initial_stacks_example() {
-        let first_stack = vec!['Z', 'N'];
-        let second_stack = vec!['M', 'C', 'D'];
-        let third_stack = vec!['P'];
-        let stacks = vec![first_stack, second_stack, third_stack];
         let (initial_stacks, _) = parse_input(EXAMPLE_INPUT);
-        assert_eq!(initial_stacks, stacks);
+        assert_eq!(initial_stacks, stacks(&[&["Z", "N"], &["M", "C", "D"], &["P"]]));
     }
 
     #[test]
@@ -154,21 +693,331 @@ mod tests {
 
     #[test]
     fn part1_example() {
-        assert_eq!(part1(&parse_input(EXAMPLE_INPUT)), "CMZ".to_string());
+        assert_eq!(part1(&parse_input(EXAMPLE_INPUT)).unwrap(), "CMZ".to_string());
     }
 
     #[test]
     fn part1_solution() {
-        assert_eq!(part1(&parse_input(PUZZLE_INPUT)), "ZWHVFWQWW".to_string());
+        assert_eq!(part1(&parse_input(PUZZLE_INPUT)).unwrap(), "ZWHVFWQWW".to_string());
     }
 
     #[test]
     fn part2_example() {
-        assert_eq!(part2(&parse_input(EXAMPLE_INPUT)), "MCD".to_string());
+        assert_eq!(part2(&parse_input(EXAMPLE_INPUT)).unwrap(), "MCD".to_string());
     }
 
     #[test]
     fn part2_solution() {
-        assert_eq!(part2(&parse_input(PUZZLE_INPUT)), "HZFZCCWWV".to_string());
+        assert_eq!(part2(&parse_input(PUZZLE_INPUT)).unwrap(), "HZFZCCWWV".to_string());
+    }
+
+    /// Generates random stacks with `n_crates` crates in total and random valid moves.
+    fn synthetic_input(n_stacks: usize, n_crates: usize, n_moves: usize, seed: u64) -> (Stacks, Vec<Move>) {
+        let mut state = seed;
+        let mut random = |bound: usize| next_random(&mut state, bound as u64) as usize;
+
+        let mut stacks = vec![Stack::new(); n_stacks];
+        for idx in 0..n_crates {
+            stacks[random(n_stacks)].push(idx.to_string());
+        }
+        let mut heights = stacks.iter().map(Vec::len).collect_vec();
+        let mut moves = Vec::new();
+        while moves.len() < n_moves {
+            let (from, to) = (random(n_stacks), random(n_stacks));
+            if heights[from] == 0 {
+                continue;
+            }
+            let amount = random(heights[from]) + 1;
+            heights[from] -= amount;
+            heights[to] += amount;
+            moves.push(Move {
+                amount,
+                from: from + 1,
+                to: to + 1,
+            });
+        }
+        (Stacks(stacks), moves)
+    }
+
+    #[test]
+    fn chunked_matches_vec() {
+        let mut inputs = vec![parse_input(EXAMPLE_INPUT), parse_input(PUZZLE_INPUT)];
+        inputs.extend((0..5).map(|seed| synthetic_input(7, 2000, 300, seed)));
+        for (stacks, moves) in inputs {
+            for crane in [&CrateMover9000 as &dyn Crane, &CrateMover9001, &BatchCrane::new(3)] {
+                assert_eq!(
+                    final_stacks_chunked(crane, &stacks, &moves).unwrap(),
+                    final_stacks(crane, &stacks, &moves).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chunked_millions_of_crates() {
+        let (stacks, moves) = synthetic_input(9, 3_000_000, 2000, 2022);
+        for crane in [&CrateMover9000 as &dyn Crane, &CrateMover9001] {
+            let mut chunked = stacks.iter().map(ChunkedStack::from).collect_vec();
+            for move_ in &moves {
+                crane.apply_chunked(&mut chunked, move_).unwrap();
+            }
+            assert_eq!(chunked.iter().map(ChunkedStack::len).sum::<usize>(), 3_000_000);
+            // Every move splits at most one chunk
+            assert!(chunked.iter().map(ChunkedStack::n_chunks).sum::<usize>() <= 9 + moves.len());
+        }
+    }
+
+    #[test]
+    fn chunks_merge_when_put_back() {
+        let stacks = stacks(&[&["1", "2", "3", "4", "5", "6"], &[], &[]]);
+        let cranes_and_moves = [
+            (
+                &CrateMover9000 as &dyn Crane,
+                ["move 4 from 1 to 2", "move 2 from 2 to 1", "move 2 from 2 to 1"],
+                "123456",
+            ),
+            (
+                &CrateMover9001,
+                ["move 4 from 1 to 2", "move 2 from 2 to 3", "move 2 from 2 to 1"],
+                "1234",
+            ),
+        ];
+        for (crane, moves, expected) in cranes_and_moves {
+            let mut chunked = stacks.iter().map(ChunkedStack::from).collect_vec();
+            for move_ in moves {
+                crane.apply_chunked(&mut chunked, &move_.parse().unwrap()).unwrap();
+            }
+            assert_eq!(chunked[0].n_chunks(), 1);
+            assert_eq!(chunked[0].iter().join(""), expected);
+        }
+    }
+
+    /// Times both stack representations on millions of crates. Run with
+    /// `cargo test --release -- --ignored --nocapture benchmark_millions_of_crates`.
+    #[test]
+    #[ignore]
+    fn benchmark_millions_of_crates() {
+        let (stacks, moves) = synthetic_input(9, 3_000_000, 2000, 2022);
+        for (name, crane) in [
+            ("CrateMover 9000", &CrateMover9000 as &dyn Crane),
+            ("CrateMover 9001", &CrateMover9001),
+        ] {
+            let start = Instant::now();
+            let chunked = final_stacks_chunked(crane, &stacks, &moves).unwrap();
+            let chunked_duration = start.elapsed();
+
+            let start = Instant::now();
+            let vec = final_stacks(crane, &stacks, &moves).unwrap();
+            let vec_duration = start.elapsed();
+
+            assert_eq!(chunked, vec);
+            println!("{name}: chunked {chunked_duration:?}, vec {vec_duration:?}");
+        }
+    }
+
+    #[test]
+    fn simulation_history() {
+        let (initial, moves) = parse_input(EXAMPLE_INPUT);
+        let history = simulate(&CrateMover9000, &initial, &moves).unwrap();
+        assert_eq!(history.len(), moves.len() + 1);
+        assert_eq!(history[0], initial);
+        assert_eq!(history[1], stacks(&[&["Z", "N", "D"], &["M", "C"], &["P"]]));
+        assert_eq!(history[2], stacks(&[&[], &["M", "C"], &["P", "D", "N", "Z"]]));
+
+        let history = simulate(&CrateMover9001, &initial, &moves).unwrap();
+        assert_eq!(history[2], stacks(&[&[], &["M", "C"], &["P", "Z", "N", "D"]]));
+    }
+
+    #[test]
+    fn batch_crane() {
+        let stacks = stacks(&[&["A", "B", "C", "D", "E"], &[]]);
+        let move_ = Move {
+            amount: 5,
+            from: 1,
+            to: 2,
+        };
+
+        let mut batched = stacks.clone();
+        BatchCrane::new(2).apply(&mut batched, &move_).unwrap();
+        assert_eq!(batched[1], ["D", "E", "B", "C", "A"]);
+
+        for (capacity, crane) in [(1, &CrateMover9000 as &dyn Crane), (5, &CrateMover9001)] {
+            let mut expected = stacks.clone();
+            crane.apply(&mut expected, &move_).unwrap();
+            let mut batched = stacks.clone();
+            BatchCrane::new(capacity).apply(&mut batched, &move_).unwrap();
+            assert_eq!(batched, expected);
+        }
+    }
+
+    #[test]
+    fn invalid_moves() {
+        let (stacks, _) = parse_input(EXAMPLE_INPUT);
+        let moves = vec![
+            Move {
+                amount: 1,
+                from: 3,
+                to: 1,
+            },
+            Move {
+                amount: 2,
+                from: 3,
+                to: 1,
+            },
+        ];
+        let error = simulate(&CrateMover9000, &stacks, &moves).unwrap_err();
+        assert_eq!(error.to_string(), "move 1 failed: stack 3 has 0 crates, can't move 2");
+        let input = (stacks.clone(), moves);
+        for part in [part1, part1_chunked, part2, part2_chunked] {
+            assert_eq!(
+                part(&input).unwrap_err().to_string(),
+                "move 1 failed: stack 3 has 0 crates, can't move 2"
+            );
+        }
+
+        let mut unchanged = stacks.clone();
+        for (from, to, nonexistent) in [(0, 1, 0), (1, 4, 4)] {
+            let move_ = Move { amount: 1, from, to };
+            let error = CrateMover9001.apply(&mut unchanged, &move_).unwrap_err();
+            assert_eq!(error, MoveError::NonexistentStack { stack: nonexistent });
+        }
+        assert_eq!(unchanged, stacks);
+    }
+
+    #[test]
+    fn empty_stacks() {
+        let (initial, _) = parse_input(EXAMPLE_INPUT);
+        let moves = vec![Move {
+            amount: 2,
+            from: 1,
+            to: 3,
+        }];
+        let stacks = final_stacks(&CrateMover9000, &initial, &moves).unwrap();
+        assert_eq!(stacks.tops(), [None, Some("D"), Some("Z")]);
+        assert_eq!(stacks.top_crates(&EmptyStacks::Skip), "DZ");
+        assert_eq!(stacks.top_crates(&EmptyStacks::Placeholder("_".to_string())), "_DZ");
+        assert_eq!(stacks.heights(), [0, 3, 3]);
+        assert_eq!(
+            stacks.to_string(),
+            ["    [D] [Z]", "    [C] [N]", "    [M] [P]", " 1   2   3 "].join("\n")
+        );
+    }
+
+    #[test]
+    fn locate_crates() {
+        let stacks = stacks(&[&["A", "B"], &["B"], &[], &["C", "B", "A"]]);
+        assert_eq!(
+            stacks.locate("B"),
+            [
+                CrateLocation { stack: 1, level: 1 },
+                CrateLocation { stack: 2, level: 0 },
+                CrateLocation { stack: 4, level: 1 },
+            ]
+        );
+        assert_eq!(stacks.locate("C"), [CrateLocation { stack: 4, level: 0 }]);
+        assert_eq!(stacks.locate("X"), []);
+    }
+
+    #[test]
+    fn final_stacks_match_history() {
+        let (initial, moves) = parse_input(PUZZLE_INPUT);
+        let history = simulate(&CrateMover9001, &initial, &moves).unwrap();
+        assert_eq!(
+            &final_stacks(&CrateMover9001, &initial, &moves).unwrap(),
+            history.last().unwrap()
+        );
+    }
+
+    #[test]
+    fn solve_example() {
+        let (initial, moves) = parse_input(EXAMPLE_INPUT);
+        for crane in [&CrateMover9000 as &dyn Crane, &CrateMover9001] {
+            let target = final_stacks(crane, &initial, &moves).unwrap();
+            let solution = solve(crane, &initial, &target, moves.len()).unwrap();
+            assert!(solution.len() <= moves.len());
+
+            // The solution can be fed back through the parser
+            let input = format!("{initial}\n\n{}", format_moves(&solution));
+            let (parsed_initial, parsed_moves) = parse_input(&input);
+            assert_eq!(parsed_moves, solution);
+            assert_eq!(final_stacks(crane, &parsed_initial, &parsed_moves).unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn solve_shortest() {
+        let start = stacks(&[&["A", "B", "C"], &[], &[]]);
+        let target = stacks(&[&[], &[], &["A", "B", "C"]]);
+        let solution = solve(&CrateMover9001, &start, &target, 5).unwrap();
+        assert_eq!(format_moves(&solution), "move 3 from 1 to 3\n");
+
+        // Reversing the order takes one move with the CrateMover 9000 but three with the CrateMover 9001
+        let target = stacks(&[&[], &[], &["C", "B", "A"]]);
+        assert_eq!(solve(&CrateMover9000, &start, &target, 5).unwrap().len(), 1);
+        assert_eq!(solve(&CrateMover9001, &start, &target, 5).unwrap().len(), 3);
+        assert_eq!(solve(&CrateMover9001, &start, &target, 2), None);
+
+        assert_eq!(solve(&CrateMover9000, &start, &start, 0), Some(vec![]));
+    }
+
+    #[test]
+    fn solve_impossible() {
+        let start = stacks(&[&["A"], &["B"]]);
+        assert_eq!(solve(&CrateMover9000, &start, &stacks(&[&["A"], &["C"]]), 10), None);
+        assert_eq!(solve(&CrateMover9000, &start, &stacks(&[&["A", "B"]]), 10), None);
+    }
+
+    #[test]
+    fn drawing_round_trip() {
+        for input in [EXAMPLE_INPUT, PUZZLE_INPUT] {
+            let (drawing, _) = input.split_once("\n\n").unwrap();
+            let (stacks, _) = parse_input(input);
+            assert_eq!(stacks.to_string(), drawing);
+            assert_eq!(drawing.parse::<Stacks>().unwrap(), stacks);
+        }
+    }
+
+    #[test]
+    fn draw_many_stacks_and_long_labels() {
+        let many_stacks = stacks(&[&["A"], &[], &["B", "C"], &[], &[], &[], &[], &[], &[], &["D"], &["E"]]);
+        let drawing = many_stacks.to_string();
+        assert_eq!(
+            drawing,
+            [
+                "        [C]                                ",
+                "[A]     [B]                         [D] [E]",
+                " 1   2   3   4   5   6   7   8   9   10  11",
+            ]
+            .join("\n")
+        );
+        assert_eq!(drawing.parse::<Stacks>().unwrap(), many_stacks);
+
+        let long_labels = stacks(&[&["AB", "C"], &["DEF"]]);
+        let drawing = long_labels.to_string();
+        assert_eq!(drawing, ["[C]        ", "[AB]  [DEF]", "  1     2  "].join("\n"));
+        assert_eq!(drawing.parse::<Stacks>().unwrap(), long_labels);
+    }
+
+    #[test]
+    fn invalid_drawings() {
+        let error = "[A] [B]\n 1   3 ".parse::<Stacks>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Footer should number the stacks from 1, found '3' for stack 2"
+        );
+
+        let error = "[A]\n    [B]\n 1   2 ".parse::<Stacks>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Crate '[A]' on line 1 is floating above an empty spot"
+        );
+
+        let error = "        [A]\n 1   2 ".parse::<Stacks>().unwrap_err();
+        assert_eq!(error.to_string(), "Crate '[A]' on line 1 is not above a stack number");
+
+        let error = " A \n 1 ".parse::<Stacks>().unwrap_err();
+        assert_eq!(error.to_string(), "'A' on line 1 is not a crate");
+
+        assert!("".parse::<Stacks>().is_err());
     }
 }
@@ -1,17 +1,228 @@
+use std::io::{self, BufReader, Read};
+
 use aoc_runner_derive::aoc;
 use itertools::Itertools;
 
+pub const START_OF_PACKET_LENGTH: usize = 4;
+pub const START_OF_MESSAGE_LENGTH: usize = 14;
+
 #[aoc(day6, part1)]
 fn part1(input: &str) -> u64 {
-    find_marker_index(input, 4)
+    find_marker_index(input.as_bytes(), START_OF_PACKET_LENGTH).unwrap() as u64
 }
 
 #[aoc(day6, part2)]
 fn part2(input: &str) -> u64 {
-    find_marker_index(input, 14)
+    find_marker_index(input.as_bytes(), START_OF_MESSAGE_LENGTH).unwrap() as u64
+}
+
+#[aoc(day6, part1, brute_force)]
+fn part1_brute_force(input: &str) -> u64 {
+    find_marker_index_brute_force(input, 4)
+}
+
+#[aoc(day6, part2, brute_force)]
+fn part2_brute_force(input: &str) -> u64 {
+    find_marker_index_brute_force(input, 14)
+}
+
+/// Returns the number of bytes processed when the first marker is complete, or `None` if there is no marker.
+pub fn find_marker_index(data: &[u8], marker_length: usize) -> Option<usize> {
+    marker_indices(data, marker_length).next()
+}
+
+/// Returns the number of bytes processed at every point where the last `marker_length` bytes are all different.
+/// Overlapping markers are all reported.
+pub fn marker_indices(data: &[u8], marker_length: usize) -> impl Iterator<Item = usize> + '_ {
+    let mut detector = MarkerDetector::new(marker_length);
+    data.iter().filter_map(move |&byte| {
+        if detector.push(byte) {
+            Some(detector.position())
+        } else {
+            None
+        }
+    })
+}
+
+/// Sliding window over a datastream that keeps track of where each byte value was last seen, so that checking
+/// whether the window holds a marker takes constant time per byte, independent of the marker length.
+#[derive(Debug, Clone)]
+pub struct MarkerDetector {
+    marker_length: usize,
+    /// One past the position where each byte value was last seen, 0 if it wasn't seen yet.
+    last_seen: [usize; 256],
+    /// Start of the longest run of different bytes that ends at the current position.
+    window_start: usize,
+    position: usize,
+}
+
+impl MarkerDetector {
+    /// Creates a detector for markers of `marker_length` different bytes. Panics if `marker_length` is 0.
+    pub fn new(marker_length: usize) -> Self {
+        assert!(marker_length > 0, "Marker length must be at least 1");
+        MarkerDetector {
+            marker_length,
+            last_seen: [0; 256],
+            window_start: 0,
+            position: 0,
+        }
+    }
+
+    /// Processes the next byte and returns whether the last `marker_length` bytes form a marker.
+    pub fn push(&mut self, byte: u8) -> bool {
+        let last_seen = &mut self.last_seen[byte as usize];
+        self.window_start = self.window_start.max(*last_seen);
+        self.position += 1;
+        *last_seen = self.position;
+        self.position - self.window_start >= self.marker_length
+    }
+
+    /// Number of bytes processed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkerKind {
+    StartOfPacket,
+    StartOfMessage,
+}
+
+/// A marker found in a datastream, with the number of bytes processed when it was complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Marker {
+    pub kind: MarkerKind,
+    pub index: usize,
+}
+
+/// Reports the first start-of-packet and start-of-message markers in a stream of bytes as soon as they are found.
+/// Reading stops once both markers are found, and the bytes are not kept in memory. [`MarkerStream::from_reader`]
+/// reads in buffered chunks, so it can read a little past the last marker.
+pub struct MarkerStream<I> {
+    bytes: I,
+    start_of_packet: Option<MarkerDetector>,
+    start_of_message: Option<MarkerDetector>,
+    pending: Option<Marker>,
+}
+
+impl<R: Read> MarkerStream<io::Bytes<BufReader<R>>> {
+    /// Reads the datastream from `reader` in buffered chunks.
+    pub fn from_reader(reader: R) -> Self {
+        Self::new(BufReader::new(reader).bytes())
+    }
+}
+
+impl<I: Iterator<Item = io::Result<u8>>> MarkerStream<I> {
+    pub fn new(bytes: I) -> Self {
+        MarkerStream {
+            bytes,
+            start_of_packet: Some(MarkerDetector::new(START_OF_PACKET_LENGTH)),
+            start_of_message: Some(MarkerDetector::new(START_OF_MESSAGE_LENGTH)),
+            pending: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = io::Result<u8>>> Iterator for MarkerStream<I> {
+    type Item = io::Result<Marker>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(marker) = self.pending.take() {
+            return Some(Ok(marker));
+        }
+
+        while self.start_of_packet.is_some() || self.start_of_message.is_some() {
+            let byte = match self.bytes.next()? {
+                Ok(byte) => byte,
+                Err(error) => return Some(Err(error)),
+            };
+            let start_of_packet = push_until_marker(&mut self.start_of_packet, byte, MarkerKind::StartOfPacket);
+            let start_of_message = push_until_marker(&mut self.start_of_message, byte, MarkerKind::StartOfMessage);
+            match (start_of_packet, start_of_message) {
+                (Some(packet), message) => {
+                    self.pending = message;
+                    return Some(Ok(packet));
+                }
+                (None, Some(message)) => return Some(Ok(message)),
+                (None, None) => (),
+            }
+        }
+        None
+    }
+}
+
+/// Feeds `byte` to the detector and removes the detector once it has found its marker.
+fn push_until_marker(detector: &mut Option<MarkerDetector>, byte: u8, kind: MarkerKind) -> Option<Marker> {
+    let found = detector.as_mut()?.push(byte);
+    if found {
+        let index = detector.take().unwrap().position();
+        Some(Marker { kind, index })
+    } else {
+        None
+    }
+}
+
+/// A message in a datastream, with the offset of its first byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub offset: usize,
+    pub payload: &'a [u8],
+}
+
+/// Splits a datastream into messages. The stream starts with a start-of-packet marker, after which each message
+/// follows a start-of-message marker and runs up to the next start-of-message marker or the end of the stream.
+/// Every start-of-message marker starts a message, so a marker that is directly followed by another marker or by the
+/// end of the stream gives an empty message. Markers never overlap each other, so each marker is searched for from
+/// the end of the previous one.
+pub struct FrameDecoder<'a> {
+    data: &'a [u8],
+    /// Start of the payload of the next message, or the position to search for the next start-of-message marker
+    /// from if `in_message` is false.
+    position: usize,
+    in_message: bool,
 }
 
-fn find_marker_index(input: &str, marker_length: u8) -> u64 {
+/// Returns a decoder for the messages in `data`, or `None` if it doesn't contain a start-of-packet marker.
+pub fn decode_frames(data: &[u8]) -> Option<FrameDecoder<'_>> {
+    let start_of_packet = find_marker_index(data, START_OF_PACKET_LENGTH)?;
+    Some(FrameDecoder {
+        data,
+        position: start_of_packet,
+        in_message: false,
+    })
+}
+
+impl<'a> Iterator for FrameDecoder<'a> {
+    type Item = Frame<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.in_message {
+            self.position += find_marker_index(&self.data[self.position..], START_OF_MESSAGE_LENGTH)?;
+            self.in_message = true;
+        }
+
+        let offset = self.position;
+        let end = match find_marker_index(&self.data[offset..], START_OF_MESSAGE_LENGTH) {
+            Some(next_marker) => {
+                self.position += next_marker;
+                offset + next_marker - START_OF_MESSAGE_LENGTH
+            }
+            None => {
+                // Searching for another start-of-message marker from the end of the stream finds nothing
+                self.position = self.data.len();
+                self.in_message = false;
+                self.data.len()
+            }
+        };
+        Some(Frame {
+            offset,
+            payload: &self.data[offset..end],
+        })
+    }
+}
+
+fn find_marker_index_brute_force(input: &str, marker_length: u8) -> u64 {
     for (idx, slice) in input.as_bytes().windows(marker_length as usize).enumerate() {
         if slice.iter().unique().count() == marker_length as usize {
             return (idx + marker_length as usize) as u64;
@@ -46,7 +257,7 @@ mod tests {
     fn part1_example() {
         EXAMPLE_PART1_INPUTS
             .iter()
-            .for_each(|(input, output)| assert_eq!(part1(*input), *output as u64))
+            .for_each(|(input, output)| assert_eq!(part1(input), *output as u64))
     }
 
     #[test]
@@ -58,11 +269,165 @@ mod tests {
     fn part2_example() {
         EXAMPLE_PART2_INPUTS
             .iter()
-            .for_each(|(input, output)| assert_eq!(part2(*input), *output as u64))
+            .for_each(|(input, output)| assert_eq!(part2(input), *output as u64))
     }
 
     #[test]
     fn part2_solution() {
         assert_eq!(part2(PUZZLE_INPUT), 2178);
     }
+
+    #[test]
+    fn matches_brute_force() {
+        for input in EXAMPLE_PART1_INPUTS
+            .iter()
+            .map(|(input, _)| *input)
+            .chain([PUZZLE_INPUT])
+        {
+            assert_eq!(part1(input), part1_brute_force(input));
+            assert_eq!(part2(input), part2_brute_force(input));
+        }
+    }
+
+    #[test]
+    fn no_marker() {
+        assert_eq!(find_marker_index(b"abcabcabc", 4), None);
+        assert_eq!(find_marker_index(b"", 1), None);
+        assert_eq!(find_marker_index(b"ab", 3), None);
+    }
+
+    #[test]
+    fn all_marker_indices() {
+        assert_eq!(marker_indices(b"aabcbcda", 3).collect::<Vec<_>>(), [4, 7, 8]);
+        assert_eq!(marker_indices(b"aaa", 1).collect::<Vec<_>>(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn stream_puzzle_input() {
+        let markers = MarkerStream::from_reader(PUZZLE_INPUT.as_bytes())
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            markers,
+            [
+                Marker {
+                    kind: MarkerKind::StartOfPacket,
+                    index: 1578
+                },
+                Marker {
+                    kind: MarkerKind::StartOfMessage,
+                    index: 2178
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn stream_small_chunks() {
+        /// Reader that returns at most 3 bytes per read.
+        struct Trickle<'a>(&'a [u8]);
+
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = buf.len().min(3).min(self.0.len());
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        for (input, output) in EXAMPLE_PART2_INPUTS {
+            let markers = MarkerStream::from_reader(Trickle(input.as_bytes()))
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(markers[1].index, output);
+        }
+    }
+
+    #[test]
+    fn stream_large_input() {
+        let noise = io::repeat(b'a').take(1_000_000);
+        let signal = "abcdefghijklmnopqrstuvwxyz".as_bytes();
+        let markers = MarkerStream::from_reader(noise.chain(signal).chain(io::repeat(b'z')))
+            .map(|marker| marker.unwrap().index)
+            .collect::<Vec<_>>();
+        assert_eq!(markers, [1_000_004, 1_000_014]);
+    }
+
+    #[test]
+    fn stream_markers_at_same_byte() {
+        let markers = MarkerStream::new(b"abcdefghijklmnopqrstuvwxyz".iter().map(|&byte| Ok(byte)))
+            .map(|marker| marker.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(markers.iter().map(|marker| marker.index).collect::<Vec<_>>(), [4, 14]);
+
+        let mut markers = MarkerStream::new("aaaa".bytes().map(Ok));
+        assert!(markers.next().is_none());
+    }
+
+    #[test]
+    fn decode_messages() {
+        let data = b"qqqqabcdaabcdefghijklmnbananaabcdefghijklmncabbageabcdefghijklmnfoo\n";
+        let frames = decode_frames(data).unwrap().collect::<Vec<_>>();
+        assert_eq!(
+            frames,
+            [
+                Frame {
+                    offset: 23,
+                    payload: b"banana"
+                },
+                Frame {
+                    offset: 43,
+                    payload: b"cabbage"
+                },
+                Frame {
+                    offset: 64,
+                    payload: b"foo\n"
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_edge_cases() {
+        assert!(decode_frames(b"aaaaaa").is_none());
+        assert_eq!(decode_frames(b"abcdaaaa").unwrap().count(), 0);
+
+        // Empty messages between two markers and after a marker at the end of the stream
+        let frames = decode_frames(b"wxyzabcdefghijklmnabcdefghijklmn")
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            frames,
+            [
+                Frame {
+                    offset: 18,
+                    payload: b""
+                },
+                Frame {
+                    offset: 32,
+                    payload: b""
+                }
+            ]
+        );
+        let frames = decode_frames(b"wxyzabcdefghijklmn").unwrap().collect::<Vec<_>>();
+        assert_eq!(
+            frames,
+            [Frame {
+                offset: 18,
+                payload: b""
+            }]
+        );
+
+        let first_frame = decode_frames(PUZZLE_INPUT.as_bytes()).unwrap().next().unwrap();
+        assert_eq!(first_frame.offset, 2178);
+    }
+
+    #[test]
+    fn arbitrary_bytes_and_lengths() {
+        let data = (0..=255).chain(0..=255).collect::<Vec<u8>>();
+        assert_eq!(find_marker_index(&data, 256), Some(256));
+        assert_eq!(find_marker_index(&data, 257), None);
+        assert_eq!(marker_indices(&data, 256).count(), 257);
+    }
 }
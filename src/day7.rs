@@ -1,58 +1,540 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    fs, io,
+    path::Path,
+};
 
+use anyhow::bail;
 use aoc_runner_derive::{aoc, aoc_generator};
+use itertools::Itertools;
 use parse_display::{Display, FromStr};
 
 #[aoc_generator(day7)]
-fn parse_input(input: &str) -> HashMap<String, u64> {
-    let mut current_dir = Vec::new();
-    let mut dir_sizes = HashMap::new();
-    for line in input.lines() {
+pub fn parse_input(input: &str) -> Dir {
+    parse_log(input).0
+}
+
+/// Reconstructs the filesystem from the terminal log and reports everything that looks off about the log. Files
+/// that are listed more than once are only counted once.
+pub fn parse_log(input: &str) -> (Dir, Vec<LogIssue>) {
+    let mut root = Dir::default();
+    let mut current_dir: Vec<String> = Vec::new();
+    let mut listed_dirs: HashSet<Vec<String>> = HashSet::new();
+    let mut is_listing = false;
+    let mut issues = Vec::new();
+
+    for (line_idx, line) in input.lines().enumerate() {
+        let mut report = |kind| {
+            issues.push(LogIssue {
+                line: line_idx + 1,
+                kind,
+            })
+        };
         if let Ok(command) = line.parse::<Command>() {
+            is_listing = false;
             match command {
                 Command::Cd(direction) => match direction {
-                    Direction::Top => current_dir = vec!["root".into()],
+                    Direction::Top => current_dir.clear(),
                     Direction::Up => {
-                        current_dir.pop();
+                        if current_dir.pop().is_none() {
+                            report(IssueKind::UpFromRoot);
+                        }
+                    }
+                    Direction::In(directory) => {
+                        let dir = dir_at_mut(&mut root, &current_dir);
+                        if !dir.dirs.contains_key(&directory) {
+                            report(IssueKind::UnknownDirectory {
+                                path: join_path(&path_string(&current_dir), &directory),
+                            });
+                        }
+                        dir.dirs.entry(directory.clone()).or_default();
+                        current_dir.push(directory);
                     }
-                    Direction::In(directory) => current_dir.push(directory),
                 },
-                Command::Ls => (), // Do nothing
+                Command::Ls => {
+                    is_listing = true;
+                    if !listed_dirs.insert(current_dir.clone()) {
+                        report(IssueKind::RepeatedListing {
+                            path: path_string(&current_dir),
+                        });
+                    }
+                }
+            }
+        } else if let Ok(directory) = line.parse::<Directory>() {
+            if !is_listing {
+                report(IssueKind::OutputWithoutListing);
             }
+            dir_at_mut(&mut root, &current_dir)
+                .dirs
+                .entry(directory.name)
+                .or_default();
         } else if let Ok(file) = line.parse::<File>() {
-            for level in 0..current_dir.len() {
-                let path = current_dir[0..=level].join("/");
-                dir_sizes
-                    .entry(path)
-                    .and_modify(|x| *x += file.size)
-                    .or_insert(file.size);
+            if !is_listing {
+                report(IssueKind::OutputWithoutListing);
             }
+            let dir = dir_at_mut(&mut root, &current_dir);
+            match dir.files.iter().find(|known_file| known_file.name == file.name) {
+                Some(known_file) if known_file.size != file.size => report(IssueKind::InconsistentFileSize {
+                    path: join_path(&path_string(&current_dir), &file.name),
+                    previous_size: known_file.size,
+                    size: file.size,
+                }),
+                Some(_) => (), // Already counted
+                None => dir.files.push(file),
+            }
+        } else {
+            report(IssueKind::UnrecognizedLine(line.to_string()));
         }
     }
 
-    dir_sizes
+    (root, issues)
+}
+
+/// Reconstructs the filesystem from the terminal log, failing on the first issue that is an error.
+pub fn try_parse_input(input: &str) -> anyhow::Result<Dir> {
+    let (root, issues) = parse_log(input);
+    if let Some(issue) = issues.iter().find(|issue| issue.severity() == Severity::Error) {
+        bail!("{issue}");
+    }
+    Ok(root)
+}
+
+/// Something suspicious in the terminal log, with the line number (starting at 1) where it was found.
+#[derive(Debug, PartialEq, Eq, Clone, Display)]
+#[display("line {line}: {kind}")]
+pub struct LogIssue {
+    pub line: usize,
+    pub kind: IssueKind,
+}
+
+impl LogIssue {
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            IssueKind::RepeatedListing { .. }
+            | IssueKind::UnknownDirectory { .. }
+            | IssueKind::OutputWithoutListing => Severity::Warning,
+            IssueKind::UpFromRoot | IssueKind::InconsistentFileSize { .. } | IssueKind::UnrecognizedLine(_) => {
+                Severity::Error
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Display)]
+pub enum IssueKind {
+    /// `ls` in a directory that was listed before. Entries that are listed again are not counted twice.
+    #[display("{path} is listed more than once")]
+    RepeatedListing { path: String },
+    /// `cd` into a directory that didn't appear in a listing of its parent.
+    #[display("cd into {path}, which was not listed in its parent directory")]
+    UnknownDirectory { path: String },
+    /// `cd ..` while already in the root directory.
+    #[display("cd .. while in the root directory")]
+    UpFromRoot,
+    /// A file that was listed before shows up with a different size. The first size is kept.
+    #[display("{path} was listed with size {previous_size} before, now with size {size}")]
+    InconsistentFileSize {
+        path: String,
+        previous_size: u64,
+        size: u64,
+    },
+    /// A file or directory entry that doesn't follow an `ls` command.
+    #[display("directory entry outside of an ls listing")]
+    OutputWithoutListing,
+    /// A line that is not a command, file or directory.
+    #[display("unrecognized line '{0}'")]
+    UnrecognizedLine(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+fn path_string(path: &[String]) -> String {
+    format!("/{}", path.join("/"))
+}
+
+/// Returns the directory at `path`, which was created while parsing the log.
+fn dir_at_mut<'a>(root: &'a mut Dir, path: &[String]) -> &'a mut Dir {
+    path.iter().fold(root, |dir, name| dir.dirs.get_mut(name).unwrap())
 }
 
 #[aoc(day7, part1)]
-fn part1(dir_sizes: &HashMap<String, u64>) -> u64 {
-    dir_sizes
-        .iter()
-        .filter_map(|(_, &size)| if size <= 100_000 { Some(size) } else { None })
+fn part1(root: &Dir) -> u64 {
+    root.dir_sizes()
+        .into_iter()
+        .filter_map(|(_, size)| if size <= 100_000 { Some(size) } else { None })
         .sum()
 }
 
 #[aoc(day7, part2)]
-fn part2(dir_sizes: &HashMap<String, u64>) -> u64 {
-    let total_disk_space = 70000000;
-    let required_free_space = 30000000;
-    let current_disk_usage = dir_sizes.iter().map(|(_, size)| size).max().unwrap();
-    let current_free_space = total_disk_space - current_disk_usage;
-    let space_to_clear = required_free_space - current_free_space;
-    dir_sizes
-        .iter()
-        .filter_map(|(_, &size)| if size >= space_to_clear { Some(size) } else { None })
-        .min()
+fn part2(root: &Dir) -> u64 {
+    plan_cleanup(root, 70000000, 30000000, CleanupMode::SingleDirectory)
         .unwrap()
+        .freed_space
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanupMode {
+    /// Delete the smallest single directory that frees up enough space.
+    SingleDirectory,
+    /// Delete as few directories as possible, none of them inside another, and of those options the one that frees
+    /// up the least space. The root directory itself is never deleted in this mode.
+    MultipleDirectories,
+}
+
+/// Directories to delete to free up space, with their paths and sizes.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CleanupPlan {
+    pub dirs: Vec<(String, u64)>,
+    pub freed_space: u64,
+}
+
+/// Plans which directories to delete so that at least `required_free_space` is available on a disk of
+/// `disk_size`. Returns `None` if that can't be done, and an empty plan if there is already enough free space.
+pub fn plan_cleanup(root: &Dir, disk_size: u64, required_free_space: u64, mode: CleanupMode) -> Option<CleanupPlan> {
+    let free_space = disk_size.checked_sub(root.size())?;
+    let space_to_clear = required_free_space.saturating_sub(free_space);
+    if space_to_clear == 0 {
+        return Some(CleanupPlan::default());
+    }
+
+    match mode {
+        CleanupMode::SingleDirectory => root
+            .dir_sizes()
+            .into_iter()
+            .filter(|(_, size)| *size >= space_to_clear)
+            .min_by_key(|(_, size)| *size)
+            .map(|(path, size)| CleanupPlan {
+                dirs: vec![(path, size)],
+                freed_space: size,
+            }),
+        CleanupMode::MultipleDirectories => CleanupSearch::new(root, space_to_clear).run(),
+    }
+}
+
+/// Search for the fewest non-nested directories that free up enough space. The directories are laid out in
+/// depth-first order, so deleting a directory means skipping ahead to the end of its subtree.
+struct CleanupSearch {
+    /// Path, size and the index just past the subtree of every directory except the root.
+    dirs: Vec<(String, u64, usize)>,
+    /// `max_freed[count][idx]` is the most space that can be freed by deleting at most `count` directories from
+    /// `idx` onwards. Only the counts up to the fewest directories that free up enough space are computed.
+    max_freed: Vec<Vec<u64>>,
+    target: u64,
+    best: Option<(u64, Vec<usize>)>,
+}
+
+impl CleanupSearch {
+    fn new(root: &Dir, target: u64) -> Self {
+        let mut dirs = Vec::new();
+        for (name, subdir) in &root.dirs {
+            collect_subtrees(subdir, join_path("/", name), &mut dirs);
+        }
+
+        // Deleting every top-level directory frees the most space, so more deletions than that never help
+        let n = dirs.len();
+        let mut max_freed = vec![vec![0; n + 1]];
+        while max_freed.len() <= root.dirs.len() && max_freed.last().unwrap()[0] < target {
+            let previous = max_freed.last().unwrap();
+            let mut current = vec![0; n + 1];
+            for idx in (0..n).rev() {
+                let (_, size, end) = dirs[idx];
+                current[idx] = current[idx + 1].max(size + previous[end]);
+            }
+            max_freed.push(current);
+        }
+
+        CleanupSearch {
+            dirs,
+            max_freed,
+            target,
+            best: None,
+        }
+    }
+
+    fn run(mut self) -> Option<CleanupPlan> {
+        let count = self.max_freed.len() - 1;
+        if self.max_freed[count][0] < self.target {
+            return None;
+        }
+        self.search(0, count, 0, &mut Vec::new());
+        let (freed_space, chosen) = self.best?;
+        Some(CleanupPlan {
+            dirs: chosen
+                .into_iter()
+                .map(|idx| (self.dirs[idx].0.clone(), self.dirs[idx].1))
+                .collect(),
+            freed_space,
+        })
+    }
+
+    fn search(&mut self, mut idx: usize, count: usize, freed: u64, chosen: &mut Vec<usize>) {
+        if freed >= self.target {
+            if self.best.as_ref().is_none_or(|(best_freed, _)| freed < *best_freed) {
+                self.best = Some((freed, chosen.clone()));
+            }
+            return;
+        }
+        if count == 0 {
+            return;
+        }
+        // Skipping directories is a loop instead of a recursive call, so the recursion is at most `count` deep
+        while idx < self.dirs.len() {
+            if freed + self.max_freed[count][idx] < self.target {
+                return;
+            }
+            if self.best.as_ref().is_some_and(|(best_freed, _)| freed >= *best_freed) {
+                return;
+            }
+            let (_, size, end) = self.dirs[idx];
+            chosen.push(idx);
+            self.search(end, count - 1, freed + size, chosen);
+            chosen.pop();
+            idx += 1;
+        }
+    }
+}
+
+/// Adds `dir` and its subdirectories in depth-first order and returns the total size of `dir`.
+fn collect_subtrees(dir: &Dir, path: String, dirs: &mut Vec<(String, u64, usize)>) -> u64 {
+    let idx = dirs.len();
+    dirs.push((path.clone(), 0, 0));
+    let mut size = dir.files.iter().map(|file| file.size).sum::<u64>();
+    for (name, subdir) in &dir.dirs {
+        size += collect_subtrees(subdir, join_path(&path, name), dirs);
+    }
+    dirs[idx].1 = size;
+    dirs[idx].2 = dirs.len();
+    size
+}
+
+/// A directory in the filesystem reconstructed from the terminal log.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Dir {
+    pub dirs: BTreeMap<String, Dir>,
+    pub files: Vec<File>,
+}
+
+impl Dir {
+    /// Total size of all files in this directory and its subdirectories.
+    pub fn size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum::<u64>() + self.dirs.values().map(Dir::size).sum::<u64>()
+    }
+
+    /// Looks up a directory by its path relative to this directory, e.g. `/a/e` or `a/e`.
+    pub fn get(&self, path: &str) -> Option<&Dir> {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(self, |dir, name| dir.dirs.get(name))
+    }
+
+    /// Iterates over this directory and all its subdirectories in depth-first order, together with their paths.
+    pub fn iter(&self) -> DirIter<'_> {
+        DirIter {
+            stack: vec![("/".to_string(), self)],
+        }
+    }
+
+    /// Returns the path and total size of this directory and all its subdirectories, in depth-first order. The
+    /// sizes are aggregated bottom-up so every file is only visited once.
+    pub fn dir_sizes(&self) -> Vec<(String, u64)> {
+        let mut sizes = Vec::new();
+        self.collect_sizes("/".to_string(), &mut sizes);
+        sizes
+    }
+
+    fn collect_sizes(&self, path: String, sizes: &mut Vec<(String, u64)>) -> u64 {
+        let idx = sizes.len();
+        sizes.push((path.clone(), 0));
+        let mut size = self.files.iter().map(|file| file.size).sum::<u64>();
+        for (name, dir) in &self.dirs {
+            size += dir.collect_sizes(join_path(&path, name), sizes);
+        }
+        sizes[idx].1 = size;
+        size
+    }
+
+    /// Renders the directory tree like `tree`, with the total size of each directory and the size of each file.
+    /// Directories are marked with a trailing `/` and entries are sorted by name.
+    pub fn render_tree(&self) -> String {
+        // The directories are rendered in the same depth-first order that `dir_sizes` lists them in
+        let mut sizes = self.dir_sizes().into_iter().map(|(_, size)| size);
+        let mut output = format!("/ ({})\n", sizes.next().unwrap());
+        self.render_tree_entries("", &mut sizes, &mut output);
+        output
+    }
+
+    fn render_tree_entries(&self, prefix: &str, sizes: &mut impl Iterator<Item = u64>, output: &mut String) {
+        let entries = self
+            .dirs
+            .iter()
+            .map(|(name, dir)| (name.as_str(), Some(dir), 0))
+            .chain(self.files.iter().map(|file| (file.name.as_str(), None, file.size)))
+            .sorted_by_key(|(name, _, _)| *name)
+            .collect_vec();
+
+        for (idx, (name, dir, size)) in entries.iter().enumerate() {
+            let is_last = idx == entries.len() - 1;
+            let (branch, indent) = if is_last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            match dir {
+                Some(dir) => {
+                    writeln!(output, "{prefix}{branch}{name}/ ({})", sizes.next().unwrap()).unwrap();
+                    dir.render_tree_entries(&format!("{prefix}{indent}"), sizes, output);
+                }
+                None => writeln!(output, "{prefix}{branch}{name} ({size})").unwrap(),
+            }
+        }
+    }
+
+    /// Renders the directory sizes like `du -h`, sorted from largest to smallest. With `max_depth` only directories
+    /// up to that many levels below this directory are listed.
+    pub fn render_du(&self, max_depth: Option<usize>) -> String {
+        self.dir_sizes()
+            .into_iter()
+            .filter(|(path, _)| max_depth.is_none_or(|max_depth| path_depth(path) <= max_depth))
+            .sorted_by(|(path_a, size_a), (path_b, size_b)| size_b.cmp(size_a).then_with(|| path_a.cmp(path_b)))
+            .map(|(path, size)| format!("{}\t{path}\n", human_size(size)))
+            .collect()
+    }
+}
+
+/// Depth-first iterator over a directory tree, created by [`Dir::iter`].
+pub struct DirIter<'a> {
+    stack: Vec<(String, &'a Dir)>,
+}
+
+impl<'a> Iterator for DirIter<'a> {
+    type Item = (String, &'a Dir);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, dir) = self.stack.pop()?;
+        for (name, child) in dir.dirs.iter().rev() {
+            self.stack.push((join_path(&path, name), child));
+        }
+        Some((path, dir))
+    }
+}
+
+fn path_depth(path: &str) -> usize {
+    path.split('/').filter(|name| !name.is_empty()).count()
+}
+
+/// Formats a size the way `du -h` does: rounded up, in powers of 1024, with one decimal below 10.
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if size < 1024 {
+        return size.to_string();
+    }
+
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    loop {
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        // Rounding up can carry over into the next format or unit, e.g. 9.99K becomes 10K and 1023.5K becomes 1.0M
+        let rounded = if value < 10.0 {
+            (value * 10.0).ceil() / 10.0
+        } else {
+            value.ceil()
+        };
+        if rounded >= 1024.0 && unit < UNITS.len() - 1 {
+            value = rounded;
+            continue;
+        }
+        return if rounded < 10.0 {
+            format!("{rounded:.1}{}", UNITS[unit])
+        } else {
+            format!("{rounded}{}", UNITS[unit])
+        };
+    }
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    if parent.ends_with('/') {
+        format!("{parent}{name}")
+    } else {
+        format!("{parent}/{name}")
+    }
+}
+
+/// Generates a terminal log that explores the whole directory tree, in the format that [`parse_input`] reads.
+/// Every directory is listed once, followed by a visit to each of its subdirectories.
+pub fn generate_transcript(root: &Dir) -> String {
+    let mut lines = vec![Command::Cd(Direction::Top).to_string()];
+    write_listing(root, &mut lines);
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn write_listing(dir: &Dir, lines: &mut Vec<String>) {
+    lines.push(Command::Ls.to_string());
+    for name in dir.dirs.keys() {
+        lines.push(Directory { name: name.clone() }.to_string());
+    }
+    for file in &dir.files {
+        lines.push(file.to_string());
+    }
+    for (name, subdir) in &dir.dirs {
+        lines.push(Command::Cd(Direction::In(name.clone())).to_string());
+        write_listing(subdir, lines);
+        lines.push(Command::Cd(Direction::Up).to_string());
+    }
+}
+
+/// Reads a directory tree from the local filesystem. Symbolic links are skipped and files are sorted by name.
+pub fn dir_from_path(path: &Path) -> io::Result<Dir> {
+    let mut dir = Dir::default();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if file_type.is_dir() {
+            dir.dirs.insert(name, dir_from_path(&entry.path())?);
+        } else if file_type.is_file() {
+            let size = entry.metadata()?.len();
+            dir.files.push(File { name, size });
+        }
+    }
+    dir.files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(dir)
+}
+
+/// Generates a synthetic directory tree that is `depth` levels deep, with `dirs_per_dir` subdirectories and
+/// `files_per_dir` files in every directory. File sizes are pseudo-random but deterministic for a given `seed`.
+pub fn synthetic_dir(depth: usize, dirs_per_dir: usize, files_per_dir: usize, seed: u64) -> Dir {
+    let mut state = seed;
+    synthetic_dir_with_state(depth, dirs_per_dir, files_per_dir, &mut state)
+}
+
+fn synthetic_dir_with_state(depth: usize, dirs_per_dir: usize, files_per_dir: usize, state: &mut u64) -> Dir {
+    let mut dir = Dir::default();
+    for idx in 0..files_per_dir {
+        // Linear congruential generator with the constants from Knuth's MMIX
+        *state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        dir.files.push(File {
+            name: format!("file{idx}.dat"),
+            size: (*state >> 33) % 1_000_000 + 1,
+        });
+    }
+    if depth > 0 {
+        for idx in 0..dirs_per_dir {
+            let subdir = synthetic_dir_with_state(depth - 1, dirs_per_dir, files_per_dir, state);
+            dir.dirs.insert(format!("dir{idx}"), subdir);
+        }
+    }
+    dir
 }
 
 #[derive(Debug, PartialEq, FromStr, Display)]
@@ -75,9 +557,9 @@ enum Direction {
 
 #[derive(Debug, PartialEq, FromStr, Display, Clone)]
 #[display("{size} {name}")]
-struct File {
-    name: String,
-    size: u64,
+pub struct File {
+    pub name: String,
+    pub size: u64,
 }
 
 #[derive(Debug, PartialEq, FromStr, Display)]
@@ -138,4 +620,339 @@ mod tests {
     fn part2_solution() {
         assert_eq!(part2(&parse_input(PUZZLE_INPUT)), 12785886);
     }
+
+    #[test]
+    fn cleanup_single_directory() {
+        let root = parse_input(EXAMPLE_INPUT);
+        let plan = plan_cleanup(&root, 70000000, 30000000, CleanupMode::SingleDirectory).unwrap();
+        assert_eq!(plan.dirs, [("/d".to_string(), 24933642)]);
+
+        let plan = plan_cleanup(&root, 48381165 + 100, 600, CleanupMode::SingleDirectory).unwrap();
+        assert_eq!(plan.dirs, [("/a/e".to_string(), 584)]);
+
+        assert_eq!(
+            plan_cleanup(&root, 100000000, 30000000, CleanupMode::SingleDirectory),
+            Some(CleanupPlan::default())
+        );
+        assert_eq!(
+            plan_cleanup(&root, 70000000, 80000000, CleanupMode::SingleDirectory),
+            None
+        );
+        assert_eq!(plan_cleanup(&root, 1000, 0, CleanupMode::SingleDirectory), None);
+    }
+
+    #[test]
+    fn cleanup_multiple_directories() {
+        let input = indoc! {"
+            $ cd /
+            $ ls
+            dir a
+            dir b
+            dir c
+            $ cd a
+            $ ls
+            dir x
+            100 f
+            $ cd x
+            $ ls
+            500 g
+            $ cd ..
+            $ cd ..
+            $ cd b
+            $ ls
+            450 h
+            $ cd ..
+            $ cd c
+            $ ls
+            2000 i
+        "};
+        let root = parse_input(input);
+        assert_eq!(root.size(), 3050);
+
+        // No subdirectory is large enough on its own, so only deleting everything works with a single directory
+        let plan = plan_cleanup(&root, 4000, 3000, CleanupMode::SingleDirectory).unwrap();
+        assert_eq!(plan.dirs, [("/".to_string(), 3050)]);
+        let plan = plan_cleanup(&root, 4000, 3000, CleanupMode::MultipleDirectories).unwrap();
+        assert_eq!(plan.dirs, [("/b".to_string(), 450), ("/c".to_string(), 2000)]);
+        assert_eq!(plan.freed_space, 2450);
+
+        // Three directories are needed, and /a/x is enough instead of all of /a
+        let plan = plan_cleanup(&root, 4000, 3600, CleanupMode::MultipleDirectories).unwrap();
+        assert_eq!(
+            plan.dirs,
+            [
+                ("/a/x".to_string(), 500),
+                ("/b".to_string(), 450),
+                ("/c".to_string(), 2000)
+            ]
+        );
+
+        // A single directory is enough
+        let plan = plan_cleanup(&root, 4000, 1500, CleanupMode::MultipleDirectories).unwrap();
+        assert_eq!(plan.dirs, [("/a".to_string(), 600)]);
+
+        assert_eq!(plan_cleanup(&root, 4000, 4100, CleanupMode::MultipleDirectories), None);
+    }
+
+    #[test]
+    fn cleanup_multiple_directories_puzzle() {
+        let root = parse_input(PUZZLE_INPUT);
+        let plan = plan_cleanup(&root, 70000000, 30000000, CleanupMode::MultipleDirectories).unwrap();
+        assert_eq!(plan.freed_space, plan.dirs.iter().map(|(_, size)| size).sum::<u64>());
+        assert!(plan.freed_space >= 30000000 - (70000000 - root.size()));
+
+        // Require more space than the largest subdirectory of the root frees up
+        let free_space = 70000000 - root.size();
+        let largest_subdir = root.dirs.values().map(Dir::size).max().unwrap();
+        let required_free_space = free_space + largest_subdir + 1;
+        let plan = plan_cleanup(&root, 70000000, required_free_space, CleanupMode::MultipleDirectories).unwrap();
+        assert!(plan.dirs.len() > 1);
+        assert!(plan.freed_space > largest_subdir);
+        for ((path_a, _), (path_b, _)) in plan.dirs.iter().tuple_combinations() {
+            assert!(!path_b.starts_with(&format!("{path_a}/")) && !path_a.starts_with(&format!("{path_b}/")));
+        }
+    }
+
+    #[test]
+    fn cleanup_multiple_directories_large_tree() {
+        // 19531 directories
+        let root = synthetic_dir(6, 5, 1, 1);
+        let total = root.size();
+        let largest_subdir = root.dirs.values().map(Dir::size).max().unwrap();
+        let plan = plan_cleanup(&root, total, largest_subdir + 1, CleanupMode::MultipleDirectories).unwrap();
+        assert!(plan.dirs.len() > 1);
+        assert!(plan.freed_space > largest_subdir);
+        assert_eq!(plan.freed_space, plan.dirs.iter().map(|(_, size)| size).sum::<u64>());
+    }
+
+    #[test]
+    fn example_tree() {
+        let root = parse_input(EXAMPLE_INPUT);
+        assert_eq!(root.size(), 48381165);
+        assert_eq!(root.get("/a").unwrap().size(), 94853);
+        assert_eq!(root.get("/a/e").unwrap().size(), 584);
+        assert_eq!(root.get("d").unwrap().size(), 24933642);
+        assert_eq!(root.get("/").unwrap().size(), root.size());
+        assert_eq!(root.get("/a/x"), None);
+
+        let a = root.get("/a").unwrap();
+        assert_eq!(
+            a.files.iter().map(|file| file.name.as_str()).collect::<Vec<_>>(),
+            ["f", "g", "h.lst"]
+        );
+        assert_eq!(a.dirs.keys().collect::<Vec<_>>(), ["e"]);
+
+        let paths = root.iter().map(|(path, _)| path).collect::<Vec<_>>();
+        assert_eq!(paths, ["/", "/a", "/a/e", "/d"]);
+        assert_eq!(
+            root.dir_sizes(),
+            [
+                ("/".to_string(), 48381165),
+                ("/a".to_string(), 94853),
+                ("/a/e".to_string(), 584),
+                ("/d".to_string(), 24933642)
+            ]
+        );
+    }
+
+    #[test]
+    fn puzzle_log_has_no_issues() {
+        assert_eq!(parse_log(EXAMPLE_INPUT).1, []);
+        assert_eq!(parse_log(PUZZLE_INPUT).1, []);
+    }
+
+    #[test]
+    fn repeated_listing_is_counted_once() {
+        let input = indoc! {"
+            $ cd /
+            $ ls
+            dir a
+            100 b.txt
+            $ cd a
+            $ ls
+            200 c.txt
+            $ cd ..
+            $ ls
+            dir a
+            100 b.txt
+        "};
+        let (root, issues) = parse_log(input);
+        assert_eq!(root.size(), 300);
+        assert_eq!(
+            issues,
+            [LogIssue {
+                line: 9,
+                kind: IssueKind::RepeatedListing { path: "/".to_string() }
+            }]
+        );
+        assert_eq!(issues[0].severity(), Severity::Warning);
+        assert!(try_parse_input(input).is_ok());
+    }
+
+    #[test]
+    fn invalid_navigation() {
+        let input = indoc! {"
+            $ cd /
+            $ cd ..
+            $ cd x
+            $ ls
+            10 y
+            $ cd ..
+            $ ls
+            20 y
+            $ cd x
+            $ ls
+            15 y
+            whatever
+        "};
+        let (root, issues) = parse_log(input);
+        assert_eq!(root.size(), 30);
+        assert_eq!(
+            issues.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "line 2: cd .. while in the root directory",
+                "line 3: cd into /x, which was not listed in its parent directory",
+                "line 10: /x is listed more than once",
+                "line 11: /x/y was listed with size 10 before, now with size 15",
+                "line 12: unrecognized line 'whatever'",
+            ]
+        );
+        assert_eq!(
+            try_parse_input(input).unwrap_err().to_string(),
+            "line 2: cd .. while in the root directory"
+        );
+    }
+
+    #[test]
+    fn output_without_listing() {
+        let (_, issues) = parse_log("$ cd /\n10 a\n");
+        assert_eq!(
+            issues,
+            [LogIssue {
+                line: 2,
+                kind: IssueKind::OutputWithoutListing
+            }]
+        );
+    }
+
+    #[test]
+    fn example_transcript_round_trip() {
+        let root = parse_input(EXAMPLE_INPUT);
+        let transcript = generate_transcript(&root);
+        assert_eq!(
+            transcript,
+            indoc! {"
+            $ cd /
+            $ ls
+            dir a
+            dir d
+            14848514 b.txt
+            8504156 c.dat
+            $ cd a
+            $ ls
+            dir e
+            29116 f
+            2557 g
+            62596 h.lst
+            $ cd e
+            $ ls
+            584 i
+            $ cd ..
+            $ cd ..
+            $ cd d
+            $ ls
+            4060174 j
+            8033020 d.log
+            5626152 d.ext
+            7214296 k
+            $ cd ..
+            "}
+        );
+        assert_eq!(parse_input(&transcript), root);
+    }
+
+    #[test]
+    fn synthetic_transcript_round_trip() {
+        let root = synthetic_dir(4, 4, 6, 2022);
+        assert_eq!(root.iter().count(), 1 + 4 + 16 + 64 + 256);
+
+        let transcript = generate_transcript(&root);
+        let (parsed, issues) = parse_log(&transcript);
+        assert_eq!(issues, []);
+        assert_eq!(parsed, root);
+        assert_eq!(parsed.dir_sizes(), root.dir_sizes());
+
+        let total_size: u64 = root.iter().flat_map(|(_, dir)| &dir.files).map(|file| file.size).sum();
+        assert_eq!(parsed.size(), total_size);
+    }
+
+    #[test]
+    fn local_directory_round_trip() {
+        let base = std::env::temp_dir().join(format!("day7-transcript-{}", std::process::id()));
+        fs::create_dir_all(base.join("a/e")).unwrap();
+        fs::create_dir_all(base.join("d")).unwrap();
+        fs::write(base.join("b.txt"), vec![0; 1500]).unwrap();
+        fs::write(base.join("a/f"), vec![0; 300]).unwrap();
+        fs::write(base.join("a/e/i"), vec![0; 42]).unwrap();
+        fs::write(base.join("d/j"), vec![0; 7]).unwrap();
+
+        let root = dir_from_path(&base).unwrap();
+        fs::remove_dir_all(&base).unwrap();
+
+        let parsed = parse_input(&generate_transcript(&root));
+        assert_eq!(
+            parsed.dir_sizes(),
+            [
+                ("/".to_string(), 1849),
+                ("/a".to_string(), 342),
+                ("/a/e".to_string(), 42),
+                ("/d".to_string(), 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn render_example_tree() {
+        assert_eq!(
+            parse_input(EXAMPLE_INPUT).render_tree(),
+            indoc! {"
+            / (48381165)
+            ├── a/ (94853)
+            │   ├── e/ (584)
+            │   │   └── i (584)
+            │   ├── f (29116)
+            │   ├── g (2557)
+            │   └── h.lst (62596)
+            ├── b.txt (14848514)
+            ├── c.dat (8504156)
+            └── d/ (24933642)
+                ├── d.ext (5626152)
+                ├── d.log (8033020)
+                ├── j (4060174)
+                └── k (7214296)
+            "}
+        );
+    }
+
+    #[test]
+    fn render_example_du() {
+        let root = parse_input(EXAMPLE_INPUT);
+        assert_eq!(root.render_du(None), "47M\t/\n24M\t/d\n93K\t/a\n584\t/a/e\n");
+        assert_eq!(root.render_du(Some(1)), "47M\t/\n24M\t/d\n93K\t/a\n");
+        assert_eq!(root.render_du(Some(0)), "47M\t/\n");
+    }
+
+    #[test]
+    fn human_sizes() {
+        assert_eq!(human_size(0), "0");
+        assert_eq!(human_size(1023), "1023");
+        assert_eq!(human_size(1024), "1.0K");
+        assert_eq!(human_size(1025), "1.1K");
+        assert_eq!(human_size(10 * 1024), "10K");
+        assert_eq!(human_size(10 * 1024 - 1), "10K");
+        assert_eq!(human_size(9 * 1024 + 1), "9.1K");
+        assert_eq!(human_size(1024 * 1024 - 1), "1.0M");
+        assert_eq!(human_size(1024 * 1024 + 1), "1.1M");
+        assert_eq!(human_size(5 * 1024 * 1024 * 1024), "5.0G");
+    }
 }
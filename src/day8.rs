@@ -1,25 +1,285 @@
+use std::fmt::Write;
+
+use anyhow::{bail, Context};
 use aoc_runner_derive::{aoc, aoc_generator};
-use ndarray::{s, Array2};
+use ndarray::{s, Array2, ArrayView1, Axis};
 
 #[aoc_generator(day8)]
-fn parse_input(input: &str) -> Array2<u8> {
-    let rows = input.lines().count();
-    let cols = input.lines().next().map(|row| row.chars().count()).unwrap();
+pub fn parse_input(input: &str) -> Array2<u32> {
+    parse_height_map(input, HeightFormat::Digits, RaggedRows::Reject).unwrap()
+}
+
+/// How the heights on a line of a height map are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeightFormat {
+    /// Single digits without separators, like the puzzle input.
+    Digits,
+    /// Numbers of any size separated by whitespace.
+    WhitespaceSeparated,
+}
+
+/// How rows that are shorter than the longest row are handled when parsing a height map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaggedRows {
+    /// Return an error for the first row with a different length.
+    Reject,
+    /// Fill the missing trees at the end of short rows with trees of the given height.
+    PadWith(u32),
+}
 
-    let mut array = Array2::<u8>::zeros((rows, cols));
+/// Parses a height map with heights written in `format`. Blank lines are ignored.
+pub fn parse_height_map(input: &str, format: HeightFormat, ragged_rows: RaggedRows) -> anyhow::Result<Array2<u32>> {
+    let rows = input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(line_idx, line)| {
+            let row =
+                parse_row(line.trim(), format).with_context(|| format!("Invalid height on line {}", line_idx + 1))?;
+            Ok((line_idx + 1, row))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
-    for (row_idx, row) in input.lines().enumerate() {
-        for (col_idx, val) in row.chars().enumerate() {
-            let val = val.to_digit(10).unwrap();
-            array[[row_idx, col_idx]] = val as u8;
+    let cols = rows
+        .iter()
+        .map(|(_, row)| row.len())
+        .max()
+        .context("Height map is empty")?;
+    let mut array = Array2::zeros((rows.len(), cols));
+    for (row_idx, (line_number, row)) in rows.iter().enumerate() {
+        if row.len() < cols {
+            match ragged_rows {
+                RaggedRows::Reject => bail!(
+                    "Row on line {line_number} has {} trees but the longest row has {cols}",
+                    row.len()
+                ),
+                RaggedRows::PadWith(height) => array.row_mut(row_idx).fill(height),
+            }
+        }
+        for (col_idx, &height) in row.iter().enumerate() {
+            array[[row_idx, col_idx]] = height;
         }
     }
 
-    array
+    Ok(array)
+}
+
+fn parse_row(line: &str, format: HeightFormat) -> anyhow::Result<Vec<u32>> {
+    match format {
+        HeightFormat::WhitespaceSeparated => line
+            .split_whitespace()
+            .enumerate()
+            .map(|(col_idx, height)| {
+                height
+                    .parse()
+                    .with_context(|| format!("'{height}' in column {} is not a number", col_idx + 1))
+            })
+            .collect(),
+        HeightFormat::Digits => line
+            .chars()
+            .enumerate()
+            .map(|(col_idx, height)| {
+                height
+                    .to_digit(10)
+                    .with_context(|| format!("'{height}' in column {} is not a digit", col_idx + 1))
+            })
+            .collect(),
+    }
 }
 
 #[aoc(day8, part1)]
-fn part1(tree_heigths: &Array2<u8>) -> u64 {
+fn part1(tree_heigths: &Array2<u32>) -> u64 {
+    visibility_map(tree_heigths).iter().filter(|&&visible| visible).count() as u64
+}
+
+#[aoc(day8, part2)]
+fn part2(tree_heigths: &Array2<u32>) -> u64 {
+    best_scenic_spot(tree_heigths).1
+}
+
+/// Returns for every tree whether it is visible from outside the grid. Each row and column is scanned once in
+/// both directions while keeping track of the tallest tree seen so far.
+pub fn visibility_map(tree_heigths: &Array2<u32>) -> Array2<bool> {
+    let mut visible = Array2::from_elem(tree_heigths.dim(), false);
+    for axis in [Axis(0), Axis(1)] {
+        for (heights, mut visible) in tree_heigths.lanes(axis).into_iter().zip(visible.lanes_mut(axis)) {
+            mark_visible(heights.iter(), visible.iter_mut());
+            mark_visible(heights.iter().rev(), visible.iter_mut().rev());
+        }
+    }
+    visible
+}
+
+fn mark_visible<'a, 'b>(heights: impl Iterator<Item = &'a u32>, visible: impl Iterator<Item = &'b mut bool>) {
+    let mut tallest = None;
+    for (&height, visible) in heights.zip(visible) {
+        if tallest.is_none_or(|tallest| height > tallest) {
+            *visible = true;
+            tallest = Some(height);
+        }
+    }
+}
+
+/// Returns the scenic score of every tree. The viewing distances are found with a monotonic stack per row and
+/// column and direction, which holds the trees that can still block the view of the trees that come after it.
+pub fn scenic_scores(tree_heigths: &Array2<u32>) -> Array2<u64> {
+    let mut scores = Array2::ones(tree_heigths.dim());
+    for axis in [Axis(0), Axis(1)] {
+        for (heights, mut scores) in tree_heigths.lanes(axis).into_iter().zip(scores.lanes_mut(axis)) {
+            multiply_viewing_distances(heights.iter(), scores.iter_mut());
+            multiply_viewing_distances(heights.iter().rev(), scores.iter_mut().rev());
+        }
+    }
+    scores
+}
+
+/// Multiplies each score by the viewing distance looking back against the iteration direction.
+fn multiply_viewing_distances<'a, 'b>(
+    heights: impl Iterator<Item = &'a u32>,
+    scores: impl Iterator<Item = &'b mut u64>,
+) {
+    let mut blocking_trees: Vec<(usize, u32)> = Vec::new();
+    for (idx, (&height, score)) in heights.zip(scores).enumerate() {
+        while blocking_trees
+            .last()
+            .is_some_and(|&(_, blocking_height)| blocking_height < height)
+        {
+            blocking_trees.pop();
+        }
+        let viewing_distance = blocking_trees
+            .last()
+            .map_or(idx, |&(blocking_idx, _)| idx - blocking_idx);
+        *score *= viewing_distance as u64;
+        blocking_trees.push((idx, height));
+    }
+}
+
+/// Returns the position and scenic score of the tree with the highest scenic score.
+pub fn best_scenic_spot(tree_heigths: &Array2<u32>) -> ((usize, usize), u64) {
+    best_score(&scenic_scores(tree_heigths))
+}
+
+fn best_score(scores: &Array2<u64>) -> ((usize, usize), u64) {
+    scores
+        .indexed_iter()
+        .max_by_key(|(_, &score)| score)
+        .map(|(position, &score)| (position, score))
+        .unwrap()
+}
+
+/// What a single tree sees when looking in each direction.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TreeView {
+    pub height: u32,
+    pub visible_from_left: bool,
+    pub visible_from_right: bool,
+    pub visible_from_top: bool,
+    pub visible_from_bottom: bool,
+    pub viewing_distance_left: usize,
+    pub viewing_distance_right: usize,
+    pub viewing_distance_top: usize,
+    pub viewing_distance_bottom: usize,
+}
+
+impl TreeView {
+    pub fn is_visible(&self) -> bool {
+        self.visible_from_left || self.visible_from_right || self.visible_from_top || self.visible_from_bottom
+    }
+
+    pub fn scenic_score(&self) -> u64 {
+        (self.viewing_distance_left
+            * self.viewing_distance_right
+            * self.viewing_distance_top
+            * self.viewing_distance_bottom) as u64
+    }
+}
+
+/// Looks up the visibility and viewing distances of the tree at `row`, `col`. Returns `None` if the position is
+/// outside of the grid.
+pub fn tree_view(tree_heigths: &Array2<u32>, row: usize, col: usize) -> Option<TreeView> {
+    let height = *tree_heigths.get([row, col])?;
+    let left = tree_heigths.slice(s![row, ..col;-1]);
+    let right = tree_heigths.slice(s![row, (col + 1)..]);
+    let top = tree_heigths.slice(s![..row;-1, col]);
+    let bottom = tree_heigths.slice(s![(row + 1).., col]);
+
+    let is_visible_over = |line: &ArrayView1<u32>| line.iter().all(|&other| other < height);
+    let viewing_distance = |line: &ArrayView1<u32>| {
+        line.iter()
+            .position(|&other| other >= height)
+            .map(|idx| idx + 1)
+            .unwrap_or(line.len())
+    };
+
+    Some(TreeView {
+        height,
+        visible_from_left: is_visible_over(&left),
+        visible_from_right: is_visible_over(&right),
+        visible_from_top: is_visible_over(&top),
+        visible_from_bottom: is_visible_over(&bottom),
+        viewing_distance_left: viewing_distance(&left),
+        viewing_distance_right: viewing_distance(&right),
+        viewing_distance_top: viewing_distance(&top),
+        viewing_distance_bottom: viewing_distance(&bottom),
+    })
+}
+
+/// Renders the forest with visible trees highlighted and the best scenic spot marked. With `colored` the trees are
+/// printed with ANSI colors (visible trees in green, hidden trees dimmed and the best scenic spot in bold yellow).
+/// Without colors hidden trees are printed as `.` and the best scenic spot as `*`.
+pub fn render_forest(tree_heigths: &Array2<u32>, colored: bool) -> String {
+    render_forest_with(
+        tree_heigths,
+        &visibility_map(tree_heigths),
+        &scenic_scores(tree_heigths),
+        colored,
+    )
+}
+
+/// Same as [`render_forest`], but with the maps from [`visibility_map`] and [`scenic_scores`] that the caller
+/// already computed.
+pub fn render_forest_with(
+    tree_heigths: &Array2<u32>,
+    visible: &Array2<bool>,
+    scores: &Array2<u64>,
+    colored: bool,
+) -> String {
+    const GREEN: &str = "\x1b[32m";
+    const DIM: &str = "\x1b[2m";
+    const BOLD_YELLOW: &str = "\x1b[1;33m";
+    const RESET: &str = "\x1b[0m";
+
+    let (best_spot, _) = best_score(scores);
+
+    // Heights with more than one digit are right-aligned and separated by spaces
+    let width = tree_heigths.iter().max().map_or(1, |height| height.to_string().len());
+    let separator = if width > 1 { " " } else { "" };
+
+    let mut output = String::new();
+    for (row_idx, row) in tree_heigths.rows().into_iter().enumerate() {
+        for (col_idx, height) in row.iter().enumerate() {
+            if col_idx > 0 {
+                output.push_str(separator);
+            }
+            let is_best_spot = (row_idx, col_idx) == best_spot;
+            let is_visible = visible[[row_idx, col_idx]];
+            match (colored, is_best_spot, is_visible) {
+                (true, true, _) => write!(output, "{BOLD_YELLOW}{height:>width$}{RESET}"),
+                (true, false, true) => write!(output, "{GREEN}{height:>width$}{RESET}"),
+                (true, false, false) => write!(output, "{DIM}{height:>width$}{RESET}"),
+                (false, true, _) => write!(output, "{:>width$}", "*"),
+                (false, false, true) => write!(output, "{height:>width$}"),
+                (false, false, false) => write!(output, "{:>width$}", "."),
+            }
+            .unwrap();
+        }
+        output.push('\n');
+    }
+    output
+}
+
+#[aoc(day8, part1, brute_force)]
+fn part1_brute_force(tree_heigths: &Array2<u32>) -> u64 {
     let rows = tree_heigths.nrows();
     let cols = tree_heigths.ncols();
 
@@ -28,10 +288,10 @@ fn part1(tree_heigths: &Array2<u8>) -> u64 {
     for i in 1..(rows - 1) {
         for j in 1..(cols - 1) {
             let val = tree_heigths[[i, j]];
-            let is_visible_from_left = tree_heigths.slice(s![i, 0..j]).iter().max().unwrap().clone() < val;
-            let is_visible_from_right = tree_heigths.slice(s![i, (j + 1)..]).iter().max().unwrap().clone() < val;
-            let is_visible_from_top = tree_heigths.slice(s![0..i, j]).iter().max().unwrap().clone() < val;
-            let is_visible_from_bottom = tree_heigths.slice(s![(i + 1).., j]).iter().max().unwrap().clone() < val;
+            let is_visible_from_left = *tree_heigths.slice(s![i, 0..j]).iter().max().unwrap() < val;
+            let is_visible_from_right = *tree_heigths.slice(s![i, (j + 1)..]).iter().max().unwrap() < val;
+            let is_visible_from_top = *tree_heigths.slice(s![0..i, j]).iter().max().unwrap() < val;
+            let is_visible_from_bottom = *tree_heigths.slice(s![(i + 1).., j]).iter().max().unwrap() < val;
 
             if is_visible_from_left || is_visible_from_right || is_visible_from_top || is_visible_from_bottom {
                 n_visible += 1;
@@ -41,8 +301,8 @@ fn part1(tree_heigths: &Array2<u8>) -> u64 {
     n_visible
 }
 
-#[aoc(day8, part2)]
-fn part2(tree_heigths: &Array2<u8>) -> u64 {
+#[aoc(day8, part2, brute_force)]
+fn part2_brute_force(tree_heigths: &Array2<u32>) -> u64 {
     let rows = tree_heigths.nrows();
     let cols = tree_heigths.ncols();
 
@@ -125,8 +385,161 @@ mod tests {
         assert_eq!(part2(&parse_input(EXAMPLE_INPUT)), 8);
     }
 
+    #[test]
+    fn best_scenic_spot_example() {
+        assert_eq!(best_scenic_spot(&parse_input(EXAMPLE_INPUT)), ((3, 2), 8));
+    }
+
     #[test]
     fn part2_solution() {
         assert_eq!(part2(&parse_input(PUZZLE_INPUT)), 201684);
     }
+
+    #[test]
+    fn single_pass_matches_brute_force() {
+        for input in [EXAMPLE_INPUT, PUZZLE_INPUT] {
+            let tree_heigths = parse_input(input);
+            assert_eq!(part1(&tree_heigths), part1_brute_force(&tree_heigths));
+            assert_eq!(part2(&tree_heigths), part2_brute_force(&tree_heigths));
+        }
+    }
+
+    #[test]
+    fn example_maps() {
+        let tree_heigths = parse_input(EXAMPLE_INPUT);
+        let visible = visibility_map(&tree_heigths).map(|&visible| visible as u8);
+        assert_eq!(
+            visible,
+            ndarray::arr2(&[
+                [1, 1, 1, 1, 1],
+                [1, 1, 1, 0, 1],
+                [1, 1, 0, 1, 1],
+                [1, 0, 1, 0, 1],
+                [1, 1, 1, 1, 1],
+            ])
+        );
+        let scores = scenic_scores(&tree_heigths);
+        assert_eq!(scores[[1, 2]], 4);
+        assert_eq!(scores[[3, 2]], 8);
+        assert_eq!(scores.row(0).sum(), 0);
+    }
+
+    #[test]
+    fn example_tree_view() {
+        let tree_heigths = parse_input(EXAMPLE_INPUT);
+        let view = tree_view(&tree_heigths, 1, 2).unwrap();
+        assert_eq!(
+            view,
+            TreeView {
+                height: 5,
+                visible_from_left: false,
+                visible_from_right: true,
+                visible_from_top: true,
+                visible_from_bottom: false,
+                viewing_distance_left: 1,
+                viewing_distance_right: 2,
+                viewing_distance_top: 1,
+                viewing_distance_bottom: 2,
+            }
+        );
+        assert_eq!(view.scenic_score(), 4);
+        assert_eq!(tree_view(&tree_heigths, 3, 2).unwrap().scenic_score(), 8);
+        assert!(!tree_view(&tree_heigths, 2, 2).unwrap().is_visible());
+        assert_eq!(tree_view(&tree_heigths, 5, 0), None);
+    }
+
+    #[test]
+    fn tree_view_matches_maps() {
+        let tree_heigths = parse_input(PUZZLE_INPUT);
+        let visible = visibility_map(&tree_heigths);
+        let scores = scenic_scores(&tree_heigths);
+        for ((row, col), _) in tree_heigths.indexed_iter() {
+            let view = tree_view(&tree_heigths, row, col).unwrap();
+            assert_eq!(view.is_visible(), visible[[row, col]]);
+            assert_eq!(view.scenic_score(), scores[[row, col]]);
+        }
+    }
+
+    #[test]
+    fn render_example() {
+        let tree_heigths = parse_input(EXAMPLE_INPUT);
+        assert_eq!(
+            render_forest(&tree_heigths, false),
+            indoc! {"
+            30373
+            255.2
+            65.32
+            3.*.9
+            35390
+            "}
+        );
+        assert!(render_forest(&tree_heigths, true).starts_with("\x1b[32m3\x1b[0m"));
+    }
+
+    #[test]
+    fn parse_whitespace_separated() {
+        let input = indoc! {"
+            30 3 70 3
+            25 5 12 2
+            6 50 3 32
+        "};
+        let tree_heigths = parse_height_map(input, HeightFormat::WhitespaceSeparated, RaggedRows::Reject).unwrap();
+        assert_eq!(
+            tree_heigths,
+            ndarray::arr2(&[[30, 3, 70, 3], [25, 5, 12, 2], [6, 50, 3, 32]])
+        );
+        assert_eq!(part1(&tree_heigths), 12);
+        assert_eq!(
+            render_forest(&tree_heigths, false),
+            indoc! {"
+            30  3 70  3
+            25  5  *  2
+             6 50  3 32
+            "}
+        );
+    }
+
+    #[test]
+    fn parse_single_column() {
+        let input = "10\n20\n30\n";
+        let tree_heigths = parse_height_map(input, HeightFormat::WhitespaceSeparated, RaggedRows::Reject).unwrap();
+        assert_eq!(tree_heigths, ndarray::arr2(&[[10], [20], [30]]));
+        let tree_heigths = parse_height_map(input, HeightFormat::Digits, RaggedRows::Reject).unwrap();
+        assert_eq!(tree_heigths, ndarray::arr2(&[[1, 0], [2, 0], [3, 0]]));
+        assert!(parse_height_map("1 2\n", HeightFormat::Digits, RaggedRows::Reject).is_err());
+    }
+
+    #[test]
+    fn parse_ragged_rows() {
+        let input = indoc! {"
+            303
+            25512
+            6533
+        "};
+        let error = parse_height_map(input, HeightFormat::Digits, RaggedRows::Reject).unwrap_err();
+        assert_eq!(error.to_string(), "Row on line 1 has 3 trees but the longest row has 5");
+
+        let tree_heigths = parse_height_map(input, HeightFormat::Digits, RaggedRows::PadWith(0)).unwrap();
+        assert_eq!(
+            tree_heigths,
+            ndarray::arr2(&[[3, 0, 3, 0, 0], [2, 5, 5, 1, 2], [6, 5, 3, 3, 0]])
+        );
+    }
+
+    #[test]
+    fn parse_invalid_heights() {
+        let error = parse_height_map("303\n2x5\n", HeightFormat::Digits, RaggedRows::Reject).unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "Invalid height on line 2: 'x' in column 2 is not a digit"
+        );
+
+        let error = parse_height_map("1 2\n3 -4\n", HeightFormat::WhitespaceSeparated, RaggedRows::Reject).unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "Invalid height on line 2: '-4' in column 2 is not a number: invalid digit found in string"
+        );
+
+        assert!(parse_height_map("\n\n", HeightFormat::Digits, RaggedRows::Reject).is_err());
+    }
 }
@@ -1,16 +1,54 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
+use anyhow::{bail, Context};
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
 use parse_display::{Display, FromStr};
 
 #[aoc_generator(day9)]
 fn parse_input(input: &str) -> Vec<Move> {
-    input.lines().map(|line| line.parse().unwrap()).collect_vec()
+    try_parse_input(input).unwrap()
+}
+
+/// Parses the moves, failing on moves that take the head outside the `i64` coordinates of the rope. The other knots
+/// trail the head, so they stay inside the grid as well.
+fn try_parse_input(input: &str) -> anyhow::Result<Vec<Move>> {
+    let mut head = Position::default();
+    input
+        .lines()
+        .enumerate()
+        .map(|(line_idx, line)| {
+            let move_ = line
+                .parse::<Move>()
+                .with_context(|| format!("Invalid move on line {}", line_idx + 1))?;
+            if i64::try_from(move_.steps).is_err() {
+                bail!("Step count {} on line {} is too large", move_.steps, line_idx + 1);
+            }
+            match head.checked_step_by(&move_.direction, move_.steps) {
+                Some(next_head) => head = next_head,
+                None => bail!(
+                    "Move {} on line {} takes the rope outside the grid",
+                    move_,
+                    line_idx + 1
+                ),
+            }
+            Ok(move_)
+        })
+        .collect()
 }
 
 #[aoc(day9, part1)]
-fn part1(moves: &Vec<Move>) -> u64 {
+fn part1(moves: &[Move]) -> u128 {
+    simulate_batched(moves, 2)
+}
+
+#[aoc(day9, part2)]
+fn part2(moves: &[Move]) -> u128 {
+    simulate_batched(moves, 10)
+}
+
+#[aoc(day9, part1, step_by_step)]
+fn part1_step_by_step(moves: &[Move]) -> u128 {
     let mut head = Position::default();
     let mut tail = Position::default();
     let mut visited_positions = HashSet::from([tail.clone()]);
@@ -22,34 +60,77 @@ fn part1(moves: &Vec<Move>) -> u64 {
         }
     }
 
-    visited_positions.len() as u64
+    visited_positions.len() as u128
 }
 
-#[aoc(day9, part2)]
-fn part2(moves: &Vec<Move>) -> u64 {
-    let mut knots = (0..=9).map(|_| Position::default()).collect_vec();
+#[aoc(day9, part2, step_by_step)]
+fn part2_step_by_step(moves: &[Move]) -> u128 {
+    simulate_step_by_step(moves, 10)
+}
+
+/// Simulates the rope one step at a time and counts the positions visited by the tail.
+fn simulate_step_by_step(moves: &[Move], n_knots: usize) -> u128 {
+    let mut knots = (0..n_knots).map(|_| Position::default()).collect_vec();
     let mut visited_positions = HashSet::from([knots.last().unwrap().clone()]);
-    for move_ in moves.iter() {
+    for move_ in moves {
         for _ in 0..move_.steps {
             process_step_rope(&mut knots, &move_.direction);
             visited_positions.insert(knots.last().unwrap().clone());
         }
     }
 
-    visited_positions.len() as u64
+    visited_positions.len() as u128
+}
+
+/// Simulates the rope step by step only until it trails the head in a straight line. From then on every knot
+/// moves in the direction of the head, so the rest of the move is applied in one go.
+fn simulate_batched(moves: &[Move], n_knots: usize) -> u128 {
+    let mut knots = (0..n_knots).map(|_| Position::default()).collect_vec();
+    let mut visited_area = VisitedArea::default();
+    visited_area.insert_point(knots.last().unwrap());
+    for move_ in moves {
+        let mut remaining_steps = move_.steps;
+        while remaining_steps > 0 {
+            if is_trailing_in_line(&knots, &move_.direction) {
+                let tail = knots.last().unwrap();
+                let mut first = tail.clone();
+                first.step(&move_.direction);
+                let mut last = tail.clone();
+                last.step_by(&move_.direction, remaining_steps);
+                visited_area.insert_segment(&first, &last);
+                for knot in knots.iter_mut() {
+                    knot.step_by(&move_.direction, remaining_steps);
+                }
+                break;
+            }
+            process_step_rope(&mut knots, &move_.direction);
+            visited_area.insert_point(knots.last().unwrap());
+            remaining_steps -= 1;
+        }
+    }
+
+    visited_area.count()
+}
+
+fn is_trailing_in_line(knots: &[Position], direction: &Direction) -> bool {
+    knots.iter().tuple_windows().all(|(knot, next_knot)| {
+        let mut expected = next_knot.clone();
+        expected.step(direction);
+        &expected == knot
+    })
 }
 
 fn process_step(head: &mut Position, tail: &mut Position, head_step_direction: &Direction) {
     head.step(head_step_direction);
-    if !tail.is_touching(&head) {
-        let direction = tail.direction_to(&head);
+    if !tail.is_touching(head) {
+        let direction = tail.direction_to(head);
         tail.step(&direction);
     }
 }
 
-fn process_step_rope(knots: &mut Vec<Position>, head_step_direction: &Direction) {
-    knots.get_mut(0).unwrap().step(head_step_direction);
-    let mut prev_knot = knots.get(0).unwrap().clone();
+fn process_step_rope(knots: &mut [Position], head_step_direction: &Direction) {
+    knots[0].step(head_step_direction);
+    let mut prev_knot = knots[0].clone();
     for knot in knots.iter_mut().skip(1) {
         if !knot.is_touching(&prev_knot) {
             let direction = knot.direction_to(&prev_knot);
@@ -59,42 +140,122 @@ fn process_step_rope(knots: &mut Vec<Position>, head_step_direction: &Direction)
     }
 }
 
+/// Set of visited positions stored as horizontal and vertical segments so that long straight moves don't have to
+/// be stored position by position. Single positions are stored as horizontal segments of length one.
+#[derive(Default, Debug)]
+struct VisitedArea {
+    horizontal: BTreeMap<i64, Vec<(i64, i64)>>,
+    vertical: BTreeMap<i64, Vec<(i64, i64)>>,
+}
+
+impl VisitedArea {
+    fn insert_point(&mut self, position: &Position) {
+        self.insert_segment(position, position);
+    }
+
+    /// Inserts all positions between `from` and `to` (inclusive). The positions must share a row or column.
+    fn insert_segment(&mut self, from: &Position, to: &Position) {
+        if from.y == to.y {
+            let segment = (from.x.min(to.x), from.x.max(to.x));
+            self.horizontal.entry(from.y).or_default().push(segment);
+        } else if from.x == to.x {
+            let segment = (from.y.min(to.y), from.y.max(to.y));
+            self.vertical.entry(from.x).or_default().push(segment);
+        } else {
+            panic!("Segment from {from} to {to} is not horizontal or vertical");
+        }
+    }
+
+    fn count(&self) -> u128 {
+        let horizontal = merge_segments(&self.horizontal);
+        let vertical = merge_segments(&self.vertical);
+
+        let horizontal_count: u128 = horizontal
+            .values()
+            .flatten()
+            .map(|(start, end)| u128::from(end.abs_diff(*start)) + 1)
+            .sum();
+        let vertical_count: u128 = vertical
+            .values()
+            .flatten()
+            .map(|(start, end)| u128::from(end.abs_diff(*start)) + 1)
+            .sum();
+
+        // Positions covered by both a horizontal and a vertical segment are counted twice
+        let mut crossings = 0;
+        for (x, segments) in vertical.iter() {
+            for (start, end) in segments {
+                crossings += horizontal
+                    .range(start..=end)
+                    .filter(|(_, row_segments)| contains(row_segments, *x))
+                    .count() as u128;
+            }
+        }
+
+        horizontal_count + vertical_count - crossings
+    }
+}
+
+/// Sorts and merges the overlapping and adjacent segments on each line.
+fn merge_segments(lines: &BTreeMap<i64, Vec<(i64, i64)>>) -> BTreeMap<i64, Vec<(i64, i64)>> {
+    lines
+        .iter()
+        .map(|(line, segments)| {
+            let mut merged: Vec<(i64, i64)> = Vec::new();
+            for &(start, end) in segments.iter().sorted() {
+                match merged.last_mut() {
+                    Some((_, last_end)) if start <= last_end.saturating_add(1) => *last_end = end.max(*last_end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            (*line, merged)
+        })
+        .collect()
+}
+
+/// Checks if `value` lies in one of the sorted, non-overlapping `segments`.
+fn contains(segments: &[(i64, i64)], value: i64) -> bool {
+    let idx = segments.partition_point(|(_, end)| *end < value);
+    segments.get(idx).map(|(start, _)| *start <= value).unwrap_or(false)
+}
+
 #[derive(Default, PartialEq, Eq, Hash, Clone, Debug, Display)]
 #[display("({x}, {y})")]
 struct Position {
-    x: i32,
-    y: i32,
+    x: i64,
+    y: i64,
 }
 
 impl Position {
     fn step(&mut self, direction: &Direction) {
-        match direction {
-            Direction::Up => self.y += 1,
-            Direction::Down => self.y -= 1,
-            Direction::Left => self.x -= 1,
-            Direction::Right => self.x += 1,
-            Direction::UpLeft => {
-                self.step(&Direction::Up);
-                self.step(&Direction::Left);
-            }
-            Direction::UpRight => {
-                self.step(&Direction::Up);
-                self.step(&Direction::Right);
-            }
-            Direction::DownLeft => {
-                self.step(&Direction::Down);
-                self.step(&Direction::Left);
-            }
-            Direction::DownRight => {
-                self.step(&Direction::Down);
-                self.step(&Direction::Right);
-            }
-            Direction::None => {} // Do nothing
-        }
+        self.step_by(direction, 1);
+    }
+
+    fn step_by(&mut self, direction: &Direction, steps: u64) {
+        *self = self
+            .checked_step_by(direction, steps)
+            .expect("moves that leave the grid are rejected when parsing");
+    }
+
+    /// Returns the position `steps` steps away in `direction`, or `None` if that is outside the `i64` grid.
+    fn checked_step_by(&self, direction: &Direction, steps: u64) -> Option<Self> {
+        let distance = i64::try_from(steps).ok()?;
+        let (x, y) = match direction {
+            Direction::Up => (Some(self.x), self.y.checked_add(distance)),
+            Direction::Down => (Some(self.x), self.y.checked_sub(distance)),
+            Direction::Left => (self.x.checked_sub(distance), Some(self.y)),
+            Direction::Right => (self.x.checked_add(distance), Some(self.y)),
+            Direction::UpLeft => (self.x.checked_sub(distance), self.y.checked_add(distance)),
+            Direction::UpRight => (self.x.checked_add(distance), self.y.checked_add(distance)),
+            Direction::DownLeft => (self.x.checked_sub(distance), self.y.checked_sub(distance)),
+            Direction::DownRight => (self.x.checked_add(distance), self.y.checked_sub(distance)),
+            Direction::None => (Some(self.x), Some(self.y)),
+        };
+        Some(Self { x: x?, y: y? })
     }
 
     fn direction_to(&self, other: &Self) -> Direction {
-        let (dist_x, dist_y) = self.distance_to(&other);
+        let (dist_x, dist_y) = self.distance_to(other);
         match (dist_x, dist_y) {
             (x, y) if x == 0 && y > 0 => Direction::Up,
             (x, y) if x == 0 && y < 0 => Direction::Down,
@@ -113,7 +274,7 @@ impl Position {
         x_distance.abs() <= 1 && y_distance.abs() <= 1
     }
 
-    fn distance_to(&self, other: &Self) -> (i32, i32) {
+    fn distance_to(&self, other: &Self) -> (i64, i64) {
         let x_distance = other.x - self.x;
         let y_distance = other.y - self.y;
         (x_distance, y_distance)
@@ -124,7 +285,7 @@ impl Position {
 #[display("{direction} {steps}")]
 struct Move {
     direction: Direction,
-    steps: u8,
+    steps: u64,
 }
 
 #[derive(FromStr, Display, Clone, Debug)]
@@ -204,6 +365,87 @@ mod tests {
         assert_eq!(part2(&parse_input(PUZZLE_INPUT)), 2593);
     }
 
+    #[test]
+    fn batched_matches_step_by_step() {
+        for input in [EXAMPLE_INPUT, LARGER_EXAMPLE_INPUT, PUZZLE_INPUT] {
+            let moves = parse_input(input);
+            for n_knots in [2, 3, 10] {
+                assert_eq!(
+                    simulate_batched(&moves, n_knots),
+                    simulate_step_by_step(&moves, n_knots)
+                );
+            }
+            assert_eq!(part1(&moves), part1_step_by_step(&moves));
+            assert_eq!(part2(&moves), part2_step_by_step(&moves));
+        }
+    }
+
+    #[test]
+    fn batched_matches_step_by_step_long_moves() {
+        let input = indoc! {"
+            R 1000
+            U 750
+            L 1500
+            D 999
+            R 2000
+            U 10
+            L 300
+            D 4000
+            R 1
+            U 3500
+        "};
+        let moves = parse_input(input);
+        for n_knots in [2, 10, 25] {
+            assert_eq!(
+                simulate_batched(&moves, n_knots),
+                simulate_step_by_step(&moves, n_knots)
+            );
+        }
+    }
+
+    #[test]
+    fn batched_huge_steps() {
+        let moves = parse_input(indoc! {"
+            R 1000000000000
+            L 1000000000000
+        "});
+        assert_eq!(simulate_batched(&moves, 2), 1_000_000_000_000);
+        assert_eq!(simulate_batched(&moves, 10), 1_000_000_000_000 - 8);
+    }
+
+    #[test]
+    fn step_count_out_of_range() {
+        assert_eq!(
+            try_parse_input("R 4\nR 18446744073709551615").unwrap_err().to_string(),
+            "Step count 18446744073709551615 on line 2 is too large"
+        );
+        assert!(try_parse_input(&format!("U {}", i64::MAX)).is_ok());
+        assert!(try_parse_input("R x").is_err());
+    }
+
+    #[test]
+    fn moves_leaving_the_grid() {
+        let input = format!("R {max}\nL {max}\nD 3\nR {max}\nR 1", max = i64::MAX);
+        assert_eq!(
+            try_parse_input(&input).unwrap_err().to_string(),
+            "Move R 1 on line 5 takes the rope outside the grid"
+        );
+        assert!(try_parse_input(&format!("L {max}\nL 1", max = i64::MAX)).is_ok());
+        assert!(try_parse_input(&format!("L {max}\nL 1\nL 1", max = i64::MAX)).is_err());
+    }
+
+    #[test]
+    fn visited_area_wider_than_i64() {
+        let max = i64::MAX as u128;
+        let moves = parse_input(&format!("R {max}\nL {max}\nL {max}"));
+        assert_eq!(part1(&moves), 2 * max - 1);
+        assert_eq!(part2(&moves), 2 * max - 17);
+
+        // Both a full row and a full column, which together are more than fit in a u64
+        let moves = parse_input(&format!("R {max}\nL {max}\nL {max}\nL 1\nU {max}\nD {max}\nD {max}"));
+        assert_eq!(part1(&moves), 4 * max - 1);
+    }
+
     #[test]
     #[ignore]
     fn part1_example_steps() {
@@ -642,11 +884,11 @@ mod tests {
         for (y, line) in map.lines().rev().enumerate() {
             for (x, c) in line.char_indices() {
                 if c == 'H' {
-                    head.x = x as i32;
-                    head.y = y as i32;
+                    head.x = x as i64;
+                    head.y = y as i64;
                 } else if c == 'T' {
-                    tail.x = x as i32;
-                    tail.y = y as i32;
+                    tail.x = x as i64;
+                    tail.y = y as i64;
                     tail_seen = true;
                 }
             }
@@ -663,8 +905,8 @@ mod tests {
             for (x, c) in line.char_indices() {
                 if c == '#' || c == 's' {
                     let pos = Position {
-                        x: x as i32,
-                        y: y as i32,
+                        x: x as i64,
+                        y: y as i64,
                     };
                     visited_positions.insert(pos);
                 }
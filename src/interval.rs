@@ -0,0 +1,336 @@
+use std::{
+    cmp::{max, min},
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+
+/// What to do with ranges like `6-4` whose start is greater than their end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReversedRanges {
+    Reject,
+    /// Swap the start and end.
+    Normalize,
+}
+
+/// A range of sections from `start` up to and including `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interval {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Interval {
+    /// Panics if `start` is greater than `end`.
+    pub fn new(start: u64, end: u64) -> Self {
+        assert!(start <= end, "interval start {start} is greater than end {end}");
+        Self { start, end }
+    }
+
+    /// Number of sections in the interval. This is a `u128` because an interval from 0 to `u64::MAX` has 2^64
+    /// sections.
+    pub fn size(&self) -> u128 {
+        u128::from(self.end - self.start) + 1
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        self.start <= value && value <= self.end
+    }
+
+    pub fn contains_interval(&self, other: &Interval) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    pub fn overlaps(&self, other: &Interval) -> bool {
+        max(self.start, other.start) <= min(self.end, other.end)
+    }
+
+    pub fn intersection(&self, other: &Interval) -> Option<Interval> {
+        self.overlaps(other)
+            .then(|| Interval::new(max(self.start, other.start), min(self.end, other.end)))
+    }
+
+    /// Parses a range like `2-4`, with optional whitespace around the numbers.
+    pub fn parse(s: &str, reversed: ReversedRanges) -> anyhow::Result<Self> {
+        let (start, end) = s
+            .split_once('-')
+            .with_context(|| format!("'{}' is not a range like '2-4'", s.trim()))?;
+        let (start, end) = (start.trim(), end.trim());
+        let start = start
+            .parse()
+            .with_context(|| format!("invalid range start '{start}'"))?;
+        let end = end.parse().with_context(|| format!("invalid range end '{end}'"))?;
+        match reversed {
+            ReversedRanges::Reject if start > end => bail!("range start {start} is greater than end {end}"),
+            _ => Ok(Self::new(min(start, end), max(start, end))),
+        }
+    }
+
+    /// Whether the intervals overlap or are directly next to each other, so that they can be merged into one.
+    fn touches(&self, other: &Interval) -> bool {
+        self.start <= other.end.saturating_add(1) && other.start <= self.end.saturating_add(1)
+    }
+}
+
+impl FromStr for Interval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Interval::parse(s, ReversedRanges::Reject)
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// A set of sections, stored as sorted intervals that don't overlap or touch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct IntervalSet {
+    intervals: Vec<Interval>,
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The merged intervals, in ascending order.
+    pub fn intervals(&self) -> &[Interval] {
+        &self.intervals
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// Total number of sections in the set, as a `u128` like [`Interval::size`].
+    pub fn coverage(&self) -> u128 {
+        self.intervals.iter().map(Interval::size).sum()
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        let idx = self.intervals.partition_point(|interval| interval.end < value);
+        self.intervals.get(idx).is_some_and(|interval| interval.contains(value))
+    }
+
+    pub fn contains_interval(&self, other: &Interval) -> bool {
+        let idx = self.intervals.partition_point(|interval| interval.end < other.start);
+        self.intervals
+            .get(idx)
+            .is_some_and(|interval| interval.contains_interval(other))
+    }
+
+    pub fn insert(&mut self, interval: Interval) {
+        // Intervals that touch the new one form a contiguous run, which is replaced by their merged interval
+        let first = self
+            .intervals
+            .partition_point(|existing| existing.end.saturating_add(1) < interval.start);
+        let last = first
+            + self.intervals[first..]
+                .iter()
+                .take_while(|existing| existing.touches(&interval))
+                .count();
+        let merged = self.intervals[first..last].iter().fold(interval, |merged, existing| {
+            Interval::new(min(merged.start, existing.start), max(merged.end, existing.end))
+        });
+        self.intervals.splice(first..last, [merged]);
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = self
+            .intervals
+            .iter()
+            .chain(&other.intervals)
+            .copied()
+            .collect::<Vec<_>>();
+        intervals.sort_unstable();
+        Self::from_sorted(intervals)
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let (a, b) = (&self.intervals[i], &other.intervals[j]);
+            intervals.extend(a.intersection(b));
+            if a.end < b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        IntervalSet { intervals }
+    }
+
+    /// The sections in `self` that are not in `other`.
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = Vec::new();
+        let mut j = 0;
+        for interval in &self.intervals {
+            let mut start = interval.start;
+            // Skip the intervals of `other` that end before this one starts
+            while j < other.intervals.len() && other.intervals[j].end < start {
+                j += 1;
+            }
+            let mut k = j;
+            while k < other.intervals.len() && other.intervals[k].start <= interval.end {
+                let cut = other.intervals[k];
+                if cut.start > start {
+                    intervals.push(Interval::new(start, cut.start - 1));
+                }
+                if cut.end >= interval.end {
+                    break;
+                }
+                start = cut.end + 1;
+                k += 1;
+            }
+            if k == other.intervals.len() || other.intervals[k].start > interval.end {
+                intervals.push(Interval::new(start, interval.end));
+            }
+        }
+        IntervalSet { intervals }
+    }
+
+    /// Merges intervals that are sorted by their start.
+    fn from_sorted(sorted: Vec<Interval>) -> IntervalSet {
+        let mut intervals: Vec<Interval> = Vec::with_capacity(sorted.len());
+        for interval in sorted {
+            match intervals.last_mut() {
+                Some(last) if last.touches(&interval) => last.end = max(last.end, interval.end),
+                _ => intervals.push(interval),
+            }
+        }
+        IntervalSet { intervals }
+    }
+}
+
+impl FromIterator<Interval> for IntervalSet {
+    fn from_iter<I: IntoIterator<Item = Interval>>(iter: I) -> Self {
+        let mut intervals = iter.into_iter().collect::<Vec<_>>();
+        intervals.sort_unstable();
+        Self::from_sorted(intervals)
+    }
+}
+
+impl From<Interval> for IntervalSet {
+    fn from(interval: Interval) -> Self {
+        IntervalSet {
+            intervals: vec![interval],
+        }
+    }
+}
+
+impl Display for IntervalSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, interval) in self.intervals.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{interval}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::next_random;
+
+    fn set(intervals: &[(u64, u64)]) -> IntervalSet {
+        intervals
+            .iter()
+            .map(|&(start, end)| Interval::new(start, end))
+            .collect()
+    }
+
+    #[test]
+    fn interval_operations() {
+        let a = Interval::new(2, 6);
+        let b = Interval::new(4, 8);
+        assert_eq!(a.size(), 5);
+        assert!(a.overlaps(&b));
+        assert_eq!(a.intersection(&b), Some(Interval::new(4, 6)));
+        assert_eq!(a.intersection(&Interval::new(7, 9)), None);
+        assert!(Interval::new(2, 8).contains_interval(&Interval::new(3, 7)));
+        assert!(!a.contains_interval(&b));
+        assert_eq!("6-6".parse::<Interval>().unwrap(), Interval::new(6, 6));
+        assert!("6-4".parse::<Interval>().is_err());
+        assert!("6".parse::<Interval>().is_err());
+
+        let everything = Interval::new(0, u64::MAX);
+        assert_eq!(everything.size(), 1 << 64);
+        assert_eq!(IntervalSet::from(everything).coverage(), 1 << 64);
+        assert_eq!(set(&[(0, 5), (3, u64::MAX)]).coverage(), 1 << 64);
+    }
+
+    #[test]
+    fn merging() {
+        let merged = set(&[(7, 9), (1, 3), (4, 5), (12, 15), (13, 14)]);
+        assert_eq!(merged.to_string(), "1-5,7-9,12-15");
+        assert_eq!(merged.coverage(), 12);
+        assert!(merged.contains(8));
+        assert!(!merged.contains(6));
+        assert!(merged.contains_interval(&Interval::new(2, 5)));
+        assert!(!merged.contains_interval(&Interval::new(5, 7)));
+
+        let mut inserted = merged.clone();
+        inserted.insert(Interval::new(6, 6));
+        assert_eq!(inserted.to_string(), "1-9,12-15");
+        inserted.insert(Interval::new(20, 20));
+        inserted.insert(Interval::new(0, 0));
+        assert_eq!(inserted.to_string(), "0-9,12-15,20-20");
+        inserted.insert(Interval::new(10, 19));
+        assert_eq!(inserted.to_string(), "0-20");
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = set(&[(1, 5), (10, 20), (30, 30)]);
+        let b = set(&[(4, 12), (15, 16), (19, 40)]);
+        assert_eq!(a.union(&b).to_string(), "1-40");
+        assert_eq!(a.intersection(&b).to_string(), "4-5,10-12,15-16,19-20,30-30");
+        assert_eq!(a.difference(&b).to_string(), "1-3,13-14,17-18");
+        assert_eq!(b.difference(&a).to_string(), "6-9,21-29,31-40");
+        assert_eq!(a.difference(&a), IntervalSet::new());
+        assert_eq!(a.difference(&IntervalSet::new()), a);
+        assert_eq!(a.intersection(&IntervalSet::new()), IntervalSet::new());
+    }
+
+    #[test]
+    fn set_operations_match_sections() {
+        // Compare against sets of individual sections for a few pseudo-random sets
+        let mut state = 7u64;
+        let mut random = |bound: u64| next_random(&mut state, bound);
+        let mut random_set = || {
+            (0..8)
+                .map(|_| {
+                    let start = random(60);
+                    Interval::new(start, start + random(8))
+                })
+                .collect::<IntervalSet>()
+        };
+        let sections = |set: &IntervalSet| (0..80).filter(|&value| set.contains(value)).collect::<Vec<_>>();
+        for _ in 0..50 {
+            let (a, b) = (random_set(), random_set());
+            let (a_sections, b_sections) = (sections(&a), sections(&b));
+            let union = (0..80)
+                .filter(|value| a_sections.contains(value) || b_sections.contains(value))
+                .collect::<Vec<_>>();
+            let intersection = (0..80)
+                .filter(|value| a_sections.contains(value) && b_sections.contains(value))
+                .collect::<Vec<_>>();
+            let difference = (0..80)
+                .filter(|value| a_sections.contains(value) && !b_sections.contains(value))
+                .collect::<Vec<_>>();
+            assert_eq!(sections(&a.union(&b)), union);
+            assert_eq!(sections(&a.intersection(&b)), intersection);
+            assert_eq!(sections(&a.difference(&b)), difference);
+            assert_eq!(a.union(&b).coverage(), union.len() as u128);
+        }
+    }
+}
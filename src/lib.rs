@@ -2,14 +2,17 @@ mod day1;
 mod day10;
 mod day11;
 mod day13;
-mod day2;
-mod day3;
-mod day4;
-mod day5;
-mod day6;
-mod day7;
-mod day8;
+pub mod day2;
+pub mod day3;
+pub mod day4;
+pub mod day5;
+pub mod day6;
+pub mod day7;
+pub mod day8;
 mod day9;
+pub mod interval;
+#[cfg(test)]
+mod test_util;
 
 use aoc_runner_derive::aoc_lib;
 
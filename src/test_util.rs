@@ -0,0 +1,10 @@
+//! Helpers shared by the tests of the different days.
+
+/// Advances a linear congruential generator with the constants from Knuth's MMIX and returns a pseudo-random
+/// number below `bound`. Used to generate deterministic synthetic inputs.
+pub fn next_random(state: &mut u64, bound: u64) -> u64 {
+    *state = state
+        .wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407);
+    (*state >> 33) % bound
+}